@@ -0,0 +1,60 @@
+use std::net::{IpAddr, SocketAddr};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AddressFamily {
+    Ipv4,
+    Ipv6,
+}
+
+// Where the address of an endpoint candidate came from
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CandidateSource {
+    Literal,  // IP address given in the endpoint
+    Dns,      // Host name resolved by DNS
+    Wildcard, // Local endpoint without an address
+}
+
+// A local or remote address which a Connection may use
+#[derive(Debug, Clone, PartialEq)]
+pub struct EndpointCandidate {
+    pub address: SocketAddr,
+    pub family: AddressFamily,
+    pub source: CandidateSource,
+}
+
+// A combination of endpoints and protocol stack which may be attempted when initiating a Connection.
+// Candidates with a higher rank are preferred by the Selection Properties.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Candidate {
+    pub remote: EndpointCandidate,
+    pub local: Option<EndpointCandidate>, // None if no local endpoint was given
+    pub protocol: &'static str,
+    pub rank: u8,
+}
+
+// The result of Preconnection::resolve(). Candidates are in the order they are raced by initiate().
+#[derive(Debug, Clone)]
+pub struct ResolvedCandidates {
+    pub local: Vec<EndpointCandidate>,
+    pub remote: Vec<EndpointCandidate>,
+    pub candidates: Vec<Candidate>,
+}
+
+impl EndpointCandidate {
+    pub fn new(address: SocketAddr, source: CandidateSource) -> EndpointCandidate {
+        EndpointCandidate {
+            address: address,
+            family: AddressFamily::of(address.ip()),
+            source: source,
+        }
+    }
+}
+
+impl AddressFamily {
+    pub fn of(address: IpAddr) -> AddressFamily {
+        match address {
+            IpAddr::V4(_) => return AddressFamily::Ipv4,
+            IpAddr::V6(_) => return AddressFamily::Ipv6,
+        }
+    }
+}
@@ -1,128 +1,603 @@
 use crate::error::TapsError;
 use crate::preconnection::Preconnection;
 use crate::preconnection::TransportInstance;
+use crate::preconnection::{connect_tcp, connect_udp};
 use crate::message::Message;
+use crate::framer::{FramerInstance, FramerResult};
+use crate::message_context::{CapacityProfile, MessageContext};
+use crate::connection_event::ConnectionEvent;
+use crate::connection_properties::{ConnectionProperties, ConnectionProperty, ConnectionState};
+use crate::socket_options;
+
+use std::collections::VecDeque;
+use std::io::ErrorKind;
+use std::net::{Shutdown, SocketAddr};
+use std::time::Duration;
 
 use async_std::{
+    future,
     prelude::*,
 };
 
-pub struct Connection<'a, T, U> {
-    preconnection: Preconnection<'a, T, U>,
+use futures::channel::mpsc::{unbounded, UnboundedReceiver, UnboundedSender};
+
+pub struct Connection<T, U> {
+    preconnection: Preconnection<T, U>,
     transport_instance: TransportInstance,
+    event_sender: Option<UnboundedSender<ConnectionEvent>>,
+    properties: ConnectionProperties,
+    framer: Box<dyn FramerInstance<T, U> + Send + Sync>,
+    // Messages parsed by the framer which have not yet been received by the application
+    received_messages: VecDeque<U>,
+    receive_finished: bool,
 }
 
-impl<'a, T, U> Connection<'a, T, U> {
+// Largest amount of data read from the transport at once, which is also the largest UDP datagram
+const RECEIVE_CHUNK_SIZE: usize = 65535;
+
+// Longest time a closing TCP connection waits for the remote endpoint to finish sending, after which it is dropped
+const CLOSE_DRAIN_TIMEOUT: Duration = Duration::from_secs(5);
+
+impl<T, U> Connection<T, U> {
     pub fn new(
-        preconnection: Preconnection<'a, T, U>,
+        preconnection: Preconnection<T, U>,
         transport_instance: TransportInstance,
-    ) -> Connection::<'a, T, U> {
+    ) -> Connection::<T, U> {
         Connection {
+            framer: preconnection.framer.new_instance(),
             preconnection: preconnection,
             transport_instance: transport_instance,
+            event_sender: None,
+            properties: ConnectionProperties {
+                state: ConnectionState::Established,
+                ..ConnectionProperties::default()
+            },
+            received_messages: VecDeque::new(),
+            receive_finished: false,
+        }
+    }
+
+    // Create a new Connection in the same group as this one, with the same properties. Connections
+    // in a group using QUIC are separate streams of a single QUIC connection, while for other
+    // protocol stacks a new connection is established to the same remote endpoint.
+    pub async fn clone_connection(&self) -> Result<Connection<T, U>, TapsError> {
+        let transport_instance;
+
+        if let Some(stream) = self.transport_instance.tcp_stream_instance.as_ref() {
+            transport_instance = connect_tcp(stream.peer_addr()?, None).await?;
+        } else if let Some(udp_instance) = self.transport_instance.udp_socket_instance.as_ref() {
+            let local_addr = SocketAddr::new(udp_instance.socket().local_addr()?.ip(), 0);
+            transport_instance = connect_udp(udp_instance.remote_addr(), Some(local_addr)).await?;
+        } else if let Some(quic_instance) = self.transport_instance.quic_stream_instance.as_ref() {
+            if quic_instance.connection.is_closed() {
+                return Err(TapsError::ConnectionClosed);
+            }
+            transport_instance = TransportInstance {
+                tcp_stream_instance: None,
+                udp_socket_instance: None,
+                quic_stream_instance: Some(quic_instance.connection.open_stream()),
+            };
+        } else {
+            return Err(TapsError::ConnectionClosed);
+        }
+
+        let mut connection = Connection::new(self.preconnection.clone(), transport_instance);
+
+        // Apply the properties of the group to the new transport instance
+        connection.set_property(ConnectionProperty::TimeoutForAborting(self.properties.timeout_for_aborting))?;
+        connection.set_property(ConnectionProperty::KeepaliveTimeout(self.properties.keepalive_timeout))?;
+        connection.set_property(ConnectionProperty::CapacityProfile(self.properties.capacity_profile))?;
+        connection.set_property(ConnectionProperty::MaxSendRate(self.properties.max_send_rate))?;
+        connection.properties = ConnectionProperties {
+            state: ConnectionState::Established,
+            ..self.properties
+        };
+
+        return Ok(connection);
+    }
+
+    pub fn get_properties(&self) -> ConnectionProperties {
+        let mut properties = self.properties;
+
+        if let Some(udp_instance) = self.transport_instance.udp_socket_instance.as_ref() {
+            // Largest payload of a single UDP datagram
+            let max_message_size = match udp_instance.remote_addr() {
+                std::net::SocketAddr::V4(_) => 65507,
+                std::net::SocketAddr::V6(_) => 65527,
+            };
+            properties.max_message_size_send = Some(max_message_size);
+            properties.max_message_size_recv = Some(max_message_size);
+        }
+
+        if let Some(quic_instance) = self.transport_instance.quic_stream_instance.as_ref() {
+            if quic_instance.connection.is_closed() {
+                properties.state = ConnectionState::Closed;
+            }
+        }
+
+        return properties;
+    }
+
+    pub fn set_property(&mut self, property: ConnectionProperty) -> Result<(), TapsError> {
+        self.apply_property(property)?;
+
+        match property {
+            ConnectionProperty::ConnectionPriority(priority) => self.properties.priority = priority,
+            ConnectionProperty::TimeoutForAborting(timeout) => self.properties.timeout_for_aborting = timeout,
+            ConnectionProperty::KeepaliveTimeout(timeout) => self.properties.keepalive_timeout = timeout,
+            ConnectionProperty::CapacityProfile(capacity_profile) => self.properties.capacity_profile = capacity_profile,
+            ConnectionProperty::MultipathPolicy(multipath_policy) => self.properties.multipath_policy = multipath_policy,
+            ConnectionProperty::MinSendRate(rate) => self.properties.min_send_rate = rate,
+            ConnectionProperty::MinRecvRate(rate) => self.properties.min_recv_rate = rate,
+            ConnectionProperty::MaxSendRate(rate) => self.properties.max_send_rate = rate,
+            ConnectionProperty::MaxRecvRate(rate) => self.properties.max_recv_rate = rate,
+            ConnectionProperty::GroupTransmissionScheduler(scheduler) => self.properties.group_transmission_scheduler = scheduler,
+        }
+
+        return Ok(());
+    }
+
+    // Apply a connection property to the transport instance
+    fn apply_property(&self, property: ConnectionProperty) -> Result<(), TapsError> {
+        let tcp_instance = self.transport_instance.tcp_stream_instance.as_ref();
+        let udp_instance = self.transport_instance.udp_socket_instance.as_ref();
+        let quic_instance = self.transport_instance.quic_stream_instance.as_ref();
+
+        // Options on a UDP socket shared with other Connections cannot be changed for a single Connection
+        let udp_socket = udp_instance.filter(|u| !u.is_demultiplexed()).map(|u| u.socket());
+
+        let result = match property {
+            // Priority, multipath policy and scheduler are local to rs_taps, and have no effect on a single path
+            ConnectionProperty::ConnectionPriority(_)
+            | ConnectionProperty::MultipathPolicy(_)
+            | ConnectionProperty::GroupTransmissionScheduler(_) => return Ok(()),
+
+            ConnectionProperty::TimeoutForAborting(timeout) => {
+                if let Some(quic_instance) = quic_instance {
+                    quic_instance.connection.set_abort_timeout(timeout);
+                    return Ok(());
+                }
+                match tcp_instance {
+                    Some(stream) => socket_options::set_tcp_user_timeout(stream, timeout),
+                    None if timeout.is_none() => return Ok(()),
+                    None => return Err(TapsError::ConnectionPropertyNotSupported),
+                }
+            },
+
+            ConnectionProperty::KeepaliveTimeout(timeout) => {
+                match tcp_instance {
+                    Some(stream) => socket_options::set_tcp_keepalive(stream, timeout),
+                    None if timeout.is_none() => return Ok(()),
+                    None => return Err(TapsError::ConnectionPropertyNotSupported),
+                }
+            },
+
+            ConnectionProperty::CapacityProfile(capacity_profile) => {
+                if let Some(quic_instance) = quic_instance {
+                    return quic_instance.connection.set_dscp(capacity_profile.dscp());
+                }
+                if let Some(stream) = tcp_instance {
+                    socket_options::set_dscp(stream, stream.local_addr()?, capacity_profile.dscp())
+                } else if let Some(socket) = udp_socket {
+                    socket_options::set_dscp(socket, socket.local_addr()?, capacity_profile.dscp())
+                } else if capacity_profile == CapacityProfile::Default {
+                    return Ok(());
+                } else {
+                    return Err(TapsError::ConnectionPropertyNotSupported);
+                }
+            },
+
+            ConnectionProperty::MaxSendRate(rate) => {
+                if let Some(stream) = tcp_instance {
+                    socket_options::set_max_pacing_rate(stream, rate)
+                } else if let Some(socket) = udp_socket {
+                    socket_options::set_max_pacing_rate(socket, rate)
+                } else if rate.is_none() {
+                    return Ok(());
+                } else {
+                    return Err(TapsError::ConnectionPropertyNotSupported);
+                }
+            },
+
+            // No protocol stack can guarantee a minimum rate, or limit the rate at which the peer sends
+            ConnectionProperty::MinSendRate(rate)
+            | ConnectionProperty::MinRecvRate(rate)
+            | ConnectionProperty::MaxRecvRate(rate) => {
+                if rate.is_none() {
+                    return Ok(());
+                }
+                return Err(TapsError::ConnectionPropertyNotSupported);
+            },
+        };
+
+        match result {
+            Ok(_) => return Ok(()),
+            Err(_) => return Err(TapsError::ConnectionPropertyNotSupported),
+        }
+    }
+
+    // Returns a stream of the events occurring on this Connection, starting with Ready.
+    // Only the most recently returned stream receives events.
+    pub fn events(&mut self) -> UnboundedReceiver<ConnectionEvent> {
+        let (event_sender, events) = unbounded();
+
+        if let Some(quic_instance) = self.transport_instance.quic_stream_instance.as_ref() {
+            quic_instance.connection.subscribe(quic_instance.stream_id, event_sender.clone());
+        }
+
+        if self.transport_instance.tcp_stream_instance.is_some()
+            || self.transport_instance.udp_socket_instance.is_some()
+            || self.transport_instance.quic_stream_instance.is_some() {
+            event_sender.unbounded_send(ConnectionEvent::Ready).ok();
+        }
+
+        self.event_sender = Some(event_sender);
+        return events;
+    }
+
+    fn emit(&self, event: ConnectionEvent) {
+        if let Some(event_sender) = self.event_sender.as_ref() {
+            event_sender.unbounded_send(event).ok();
         }
     }
 
     pub async fn send(&mut self, message: Message<T>) -> Result<(), TapsError> {
-        let send_data: Vec<u8> = self.preconnection.framer.new_sent_message(message);
+        let message_context = message.message_context().unwrap_or(MessageContext::new());
+        let send_data = match self.framer.new_sent_message(message) {
+            Ok(send_data) => send_data,
+            Err(e) => {
+                self.emit(ConnectionEvent::SendError(message_context, TapsError::MessageSendFailed));
+                return Err(e);
+            },
+        };
 
+        // Messages which cannot start being sent within their lifetime expire
+        let result = self.send_message(send_data, &message_context).await;
+
+        match result {
+            Ok(_) => {
+                self.emit(ConnectionEvent::Sent(message_context));
+                return Ok(());
+            },
+            Err(TapsError::MessageExpired) => {
+                self.emit(ConnectionEvent::Expired(message_context));
+                return Err(TapsError::MessageExpired);
+            },
+            Err(TapsError::MessagePropertyNotSupported) => {
+                self.emit(ConnectionEvent::SendError(message_context, TapsError::MessagePropertyNotSupported));
+                return Err(TapsError::MessagePropertyNotSupported);
+            },
+            Err(TapsError::ConnectionAborted) => {
+                self.emit(ConnectionEvent::SendError(message_context, TapsError::MessageSendFailed));
+                self.fail();
+                return Err(TapsError::ConnectionAborted);
+            },
+            Err(e) => {
+                self.emit(ConnectionEvent::SendError(message_context, TapsError::MessageSendFailed));
+                return Err(e);
+            },
+        }
+    }
+
+    async fn send_message(&self, message_data: Vec<u8>, message_context: &MessageContext) -> Result<(), TapsError> {
         if self.transport_instance.tcp_stream_instance.is_some() {
-            return self.send_message_tcp(send_data).await;
+            return self.send_message_tcp(message_data, message_context).await;
         } else if self.transport_instance.udp_socket_instance.is_some() {
-            return self.send_message_udp(send_data).await;
+            return self.send_message_udp(message_data, message_context).await;
         } else if self.transport_instance.quic_stream_instance.is_some() {
-            return self.send_message_quic(send_data).await;
+            return self.send_message_quic(message_data, message_context).await;
         }
 
-        return Ok(())
+        return Err(TapsError::MessageSendFailed);
     }
 
     pub async fn receive(&mut self) -> Result<Message<U>, TapsError> {
-        let mut result = Err(TapsError::MessageReceiveFailed);
+        let message = match self.receive_message().await {
+            Ok(message) => message,
+            Err(TapsError::ConnectionClosed) => {
+                self.properties.state = ConnectionState::Closed;
+                self.emit(ConnectionEvent::Closed);
+                return Err(TapsError::ConnectionClosed);
+            },
+            Err(TapsError::ConnectionAborted) => {
+                self.fail();
+                return Err(TapsError::ConnectionAborted);
+            },
+            Err(e) => {
+                self.emit(ConnectionEvent::ReceiveError(MessageContext::new(), TapsError::MessageReceiveFailed));
+                return Err(e);
+            },
+        };
 
-        if self.transport_instance.tcp_stream_instance.is_some() {
-            result = self.receive_message_tcp().await;
-        } else if self.transport_instance.udp_socket_instance.is_some() {
-            result = self.receive_message_udp().await;
-        } else if self.transport_instance.quic_stream_instance.is_some() {
-            result = self.receive_message_quic().await;
+        if !self.receives_partial_messages() {
+            self.emit(ConnectionEvent::Received(MessageContext::new()));
         }
+        return Ok(Message::<U>::new(message, None));
+    }
 
-        let message_data = match result {
-            Ok(message_data) => message_data,
-            Err(e) => return Err(e),
-        };
+    // Without a framer delimiting messages, data read from a stream is delivered as it arrives, so is only
+    // part of a message. Each UDP datagram is a whole message.
+    fn receives_partial_messages(&self) -> bool {
+        return !self.framer.delimits_messages() && self.transport_instance.udp_socket_instance.is_none();
+    }
 
-        return Ok(Message::<U>::new(self.preconnection.framer.handle_received_data(message_data), None));
+    // A transport error has ended the connection
+    fn fail(&mut self) {
+        self.properties.state = ConnectionState::Closed;
+        self.emit(ConnectionEvent::ConnectionError(TapsError::ConnectionAborted));
     }
 
-    pub async fn close(&self) -> Result<(), TapsError> {
-        todo!();
+    pub async fn close(&mut self) -> Result<(), TapsError> {
+        let mut result = Ok(());
+        self.properties.state = ConnectionState::Closing;
+
+        if self.transport_instance.tcp_stream_instance.is_some() {
+            result = self.close_tcp().await;
+        } else if self.transport_instance.udp_socket_instance.is_some() {
+            result = self.close_udp().await;
+        } else if self.transport_instance.quic_stream_instance.is_some() {
+            result = self.close_quic().await;
+        }
+
+        if result.is_ok() {
+            self.properties.state = ConnectionState::Closed;
+            self.emit(ConnectionEvent::Closed);
+        }
+        return result;
     }
 
     pub fn abort(self) {
         drop(self);
     }
 
-    async fn send_message_tcp(&self, message_data: Vec<u8>) -> Result<(), TapsError> {
-        match self.transport_instance.tcp_stream_instance.as_ref().unwrap().write(&message_data).await {
-            Ok(_) => return Ok(()),
+    // Messages use the Connection's capacity profile unless they set their own
+    fn capacity_profile(&self, message_context: &MessageContext) -> CapacityProfile {
+        if message_context.capacity_profile == CapacityProfile::Default {
+            return self.properties.capacity_profile;
+        }
+        return message_context.capacity_profile;
+    }
+
+    async fn send_message_tcp(&self, message_data: Vec<u8>, message_context: &MessageContext) -> Result<(), TapsError> {
+        let mut stream = self.transport_instance.tcp_stream_instance.as_ref().unwrap();
+
+        // TCP always uses full checksum coverage, and may segment a message at any point
+        if message_context.checksum_len.is_some() || message_context.no_segmentation {
+            return Err(TapsError::MessagePropertyNotSupported);
+        }
+
+        let local_addr = match stream.local_addr() {
+            Ok(local_addr) => local_addr,
             Err(_) => return Err(TapsError::MessageSendFailed),
+        };
+        if socket_options::set_dscp(stream, local_addr, self.capacity_profile(message_context).dscp()).is_err() {
+            return Err(TapsError::MessagePropertyNotSupported);
+        }
+
+        // A message which is partly written cannot be withdrawn, so it may only expire while none
+        // of it has been written
+        let written = match message_context.lifetime {
+            Some(lifetime) => match future::timeout(lifetime, stream.write(&message_data)).await {
+                Ok(Ok(written)) => written,
+                Ok(Err(_)) => return Err(TapsError::ConnectionAborted),
+                Err(_) => return Err(TapsError::MessageExpired),
+            },
+            None => 0,
+        };
+
+        // Errors writing to the stream, such as a reset, end the connection
+        if stream.write_all(&message_data[written..]).await.is_err() {
+            return Err(TapsError::ConnectionAborted);
+        }
+
+        // No more messages will be sent after a final message
+        if message_context.final_message && stream.shutdown(Shutdown::Write).is_err() {
+            return Err(TapsError::MessageSendFailed);
         }
+
+        return Ok(());
     }
 
-    async fn send_message_udp(&self, message_data: Vec<u8>) -> Result<(), TapsError> {
-        match self.transport_instance.udp_socket_instance.as_ref().unwrap().send(&message_data).await {
+    async fn send_message_udp(&self, message_data: Vec<u8>, message_context: &MessageContext) -> Result<(), TapsError> {
+        let udp_instance = self.transport_instance.udp_socket_instance.as_ref().unwrap();
+
+        // UDP provides neither reliability nor ordering, and has no partial checksum coverage without UDP-Lite
+        if message_context.checksum_len.is_some() || message_context.reliable == Some(true) || message_context.ordered == Some(true) {
+            return Err(TapsError::MessagePropertyNotSupported);
+        }
+
+        // Options on a socket shared with other Connections cannot be changed for a single message
+        if udp_instance.is_demultiplexed() {
+            if message_context.capacity_profile != CapacityProfile::Default || message_context.no_fragmentation {
+                return Err(TapsError::MessagePropertyNotSupported);
+            }
+        } else {
+            let local_addr = match udp_instance.socket().local_addr() {
+                Ok(local_addr) => local_addr,
+                Err(_) => return Err(TapsError::MessageSendFailed),
+            };
+            if socket_options::set_dscp(udp_instance.socket(), local_addr, self.capacity_profile(message_context).dscp()).is_err() {
+                return Err(TapsError::MessagePropertyNotSupported);
+            }
+            if socket_options::set_dont_fragment(udp_instance.socket(), local_addr, message_context.no_fragmentation).is_err() {
+                return Err(TapsError::MessagePropertyNotSupported);
+            }
+        }
+
+        // Datagrams are sent whole, so waiting to send one can always be abandoned
+        let result = match message_context.lifetime {
+            Some(lifetime) => match future::timeout(lifetime, udp_instance.send(&message_data)).await {
+                Ok(result) => result,
+                Err(_) => return Err(TapsError::MessageExpired),
+            },
+            None => udp_instance.send(&message_data).await,
+        };
+
+        match result {
             Ok(_) => return Ok(()),
             Err(_) => return Err(TapsError::MessageSendFailed),
         }
     }
 
-    async fn send_message_quic(&mut self, message_data: Vec<u8>) -> Result<(), TapsError> {
-        let quic_instance = self.transport_instance.quic_stream_instance.as_mut().unwrap();
-        let mut conn = quic_instance.as_mut();
+    async fn send_message_quic(&self, message_data: Vec<u8>, message_context: &MessageContext) -> Result<(), TapsError> {
+        let quic_instance = self.transport_instance.quic_stream_instance.as_ref().unwrap();
 
         // Handshake not completed
-        if !conn.is_established() {
+        if !quic_instance.connection.is_established() {
             return Err(TapsError::MessageSendFailed);
         }
 
-        match conn.stream_send(0, &message_data, true) {
-            Ok(_) => return Ok(()),
-            Err(_) => return Err(TapsError::MessageSendFailed),
-        }        
+        // QUIC may split stream data across packets, and packets for all streams share one socket,
+        // so per-message DSCP marking is not possible
+        if message_context.checksum_len.is_some() || message_context.no_segmentation || message_context.capacity_profile != CapacityProfile::Default {
+            return Err(TapsError::MessagePropertyNotSupported);
+        }
+
+        quic_instance.connection.stream_priority(quic_instance.stream_id, message_context.priority)?;
+
+        // A final message ends the stream
+        return quic_instance.connection.stream_send(quic_instance.stream_id, &message_data, message_context.final_message, message_context.lifetime).await;
     }
 
-    async fn receive_message_tcp(&self) -> Result<Vec<u8>, TapsError> {
+    async fn close_tcp(&mut self) -> Result<(), TapsError> {
+        let mut stream = self.transport_instance.tcp_stream_instance.as_ref().unwrap();
         let mut buf = vec![0u8; 1024];
+
+        // Flush any pending sends before half-closing the sending side of the connection
+        if stream.flush().await.is_err() || stream.shutdown(Shutdown::Write).is_err() {
+            return Err(TapsError::ConnectionCloseFailed);
+        }
+
+        // The connection is closed once the remote endpoint has also finished sending, or is dropped if
+        // it has not done so in time
+        let drained = future::timeout(CLOSE_DRAIN_TIMEOUT, async {
+            loop {
+                match stream.read(&mut buf).await {
+                    Ok(0) => return Ok(()),
+                    Ok(_) => continue,
+                    Err(_) => return Err(TapsError::ConnectionCloseFailed),
+                }
+            }
+        }).await;
+        if let Ok(Err(e)) = drained {
+            return Err(e);
+        }
+
+        self.transport_instance.tcp_stream_instance = None;
+        return Ok(());
+    }
+
+    async fn close_udp(&mut self) -> Result<(), TapsError> {
+        // UDP has no connection state shared with the remote endpoint, so dropping the socket is sufficient
+        self.transport_instance.udp_socket_instance = None;
+        return Ok(());
+    }
+
+    async fn close_quic(&mut self) -> Result<(), TapsError> {
+        let quic_instance = self.transport_instance.quic_stream_instance.as_ref().unwrap();
+
+        // Other Connections in the group may still be using the QUIC connection, in which case only this
+        // Connection's stream is finished. Otherwise the connection's driver sends CONNECTION_CLOSE.
+        quic_instance.connection.close_stream(quic_instance.stream_id).await?;
+
+        self.transport_instance.quic_stream_instance = None;
+        return Ok(());
+    }
+
+    // Read from the transport until the framer has parsed a complete message
+    async fn receive_message(&mut self) -> Result<U, TapsError> {
+        loop {
+            if let Some(message) = self.received_messages.pop_front() {
+                return Ok(message);
+            }
+
+            // Data left over when the remote endpoint finishes sending may form a final message
+            if self.receive_finished {
+                match self.framer.handle_end_of_data()? {
+                    Some(message) => return Ok(message),
+                    None => return Err(TapsError::ConnectionClosed),
+                }
+            }
+
+            let received = if self.transport_instance.tcp_stream_instance.is_some() {
+                self.receive_message_tcp().await
+            } else if self.transport_instance.udp_socket_instance.is_some() {
+                self.receive_message_udp().await
+            } else if self.transport_instance.quic_stream_instance.is_some() {
+                self.receive_message_quic().await
+            } else {
+                Err(TapsError::MessageReceiveFailed)
+            };
+
+            match received {
+                Ok(data) => {
+                    if self.receives_partial_messages() {
+                        self.emit(ConnectionEvent::ReceivedPartial(Message::new(data.clone(), None), MessageContext::new(), self.receive_finished));
+                    }
+
+                    match self.framer.handle_received_data(&data) {
+                        Ok(FramerResult::Messages(messages)) => self.received_messages.extend(messages),
+                        Ok(FramerResult::NeedMoreData) => (),
+                        // What has been received of a message which is too large is delivered before the error
+                        Err(TapsError::MessageTooLarge) => {
+                            let partial_data = self.framer.take_partial_data();
+                            self.emit(ConnectionEvent::ReceivedPartial(Message::new(partial_data, None), MessageContext::new(), false));
+                            return Err(TapsError::MessageTooLarge);
+                        },
+                        Err(e) => return Err(e),
+                    }
+                },
+                Err(TapsError::ConnectionClosed) => self.receive_finished = true,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    async fn receive_message_tcp(&self) -> Result<Vec<u8>, TapsError> {
+        let mut buf = vec![0u8; RECEIVE_CHUNK_SIZE];
         match self.transport_instance.tcp_stream_instance.as_ref().unwrap().read(&mut buf).await {
-            Ok(_) => return Ok(buf),
-            Err(_) => return Err(TapsError::MessageReceiveFailed),
+            // The remote endpoint has closed the connection
+            Ok(0) => return Err(TapsError::ConnectionClosed),
+            Ok(len) => {
+                buf.truncate(len);
+                return Ok(buf);
+            },
+            // Errors reading from the stream, such as a reset, end the connection
+            Err(_) => return Err(TapsError::ConnectionAborted),
         }
     }
 
-    async fn receive_message_udp(&self) -> Result<Vec<u8>, TapsError> {
-        let mut buf = vec![0u8; 1024];
-        match self.transport_instance.udp_socket_instance.as_ref().unwrap().recv(&mut buf).await {
-            Ok(_) => return Ok(buf),
-            Err(_) => return Err(TapsError::MessageReceiveFailed),
+    async fn receive_message_udp(&mut self) -> Result<Vec<u8>, TapsError> {
+        let mut buf = vec![0u8; RECEIVE_CHUNK_SIZE];
+        loop {
+            match self.transport_instance.udp_socket_instance.as_mut().unwrap().recv(&mut buf).await {
+                Ok(len) => {
+                    buf.truncate(len);
+                    return Ok(buf);
+                },
+                // ICMP port unreachable from a previous send is reported on the next read, and does not end the connection
+                Err(e) if e.kind() == ErrorKind::ConnectionRefused => self.emit(ConnectionEvent::SoftError(TapsError::IcmpError)),
+                Err(_) => return Err(TapsError::MessageReceiveFailed),
+            }
         }
     }
 
     async fn receive_message_quic(&mut self) -> Result<Vec<u8>, TapsError> {
-        let mut buf = vec![0u8; 1024];
-        let conn = self.transport_instance.quic_stream_instance.as_mut().unwrap();
-        let mut read = false;
+        let mut buf = vec![0u8; RECEIVE_CHUNK_SIZE];
+        let quic_instance = self.transport_instance.quic_stream_instance.as_ref().unwrap();
+
+        // Waits until the connection's driver has received data on this stream
+        let (len, fin) = quic_instance.connection.stream_recv(quic_instance.stream_id, &mut buf).await?;
 
-        for stream_id in conn.readable() {
-            if conn.stream_recv(stream_id, &mut buf).is_ok() {
-                read = true;
-                break;
+        // The peer has finished sending on the stream, after which it cannot be read from again
+        if fin {
+            self.receive_finished = true;
+            if len == 0 {
+                return Err(TapsError::ConnectionClosed);
             }
         }
-        if read == false {
-            return Err(TapsError::MessageReceiveFailed);
-        }
+
+        buf.truncate(len);
         return Ok(buf);
     }
 }
@@ -0,0 +1,23 @@
+use crate::error::TapsError;
+use crate::message::Message;
+use crate::message_context::MessageContext;
+
+use std::net::SocketAddr;
+
+// Events delivered on the stream returned by Connection::events(), modelled on the TAPS API.
+// Message data itself is returned by Connection::receive(); Received events carry its context.
+// ReceivedPartial events carry data which is not a complete message, with whether it ends the message.
+#[derive(Debug)]
+pub enum ConnectionEvent {
+    Ready,
+    Sent(MessageContext),
+    Expired(MessageContext),
+    SendError(MessageContext, TapsError),
+    Received(MessageContext),
+    ReceivedPartial(Message<Vec<u8>>, MessageContext, bool), // Partial data, and whether it ends the message
+    ReceiveError(MessageContext, TapsError),
+    SoftError(TapsError),
+    PathChange(SocketAddr), // New remote address
+    ConnectionError(TapsError),
+    Closed,
+}
@@ -0,0 +1,84 @@
+use crate::message_context::CapacityProfile;
+
+use std::time::Duration;
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum ConnectionState {
+    Establishing,
+    Established,
+    Closing,
+    Closed,
+}
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum MultipathPolicy {
+    Handover,
+    Interactive,
+    Aggregate,
+}
+
+// Schedulers for sharing capacity between the Connections of a group, as defined in RFC 8260
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum SchedulerType {
+    FirstComeFirstServed,
+    RoundRobin,
+    RoundRobinPerPacket,
+    Priority,
+    FairCapacity,
+    WeightedFairQueueing,
+}
+
+// Connection properties which can be changed with Connection::set_property
+#[derive(Debug, Copy, Clone)]
+pub enum ConnectionProperty {
+    ConnectionPriority(u32),
+    TimeoutForAborting(Option<Duration>),
+    KeepaliveTimeout(Option<Duration>),
+    CapacityProfile(CapacityProfile),
+    MultipathPolicy(MultipathPolicy),
+    MinSendRate(Option<u64>), // Bits per second, None for no limit
+    MinRecvRate(Option<u64>),
+    MaxSendRate(Option<u64>),
+    MaxRecvRate(Option<u64>),
+    GroupTransmissionScheduler(SchedulerType),
+}
+
+// Current values of all connection properties, as returned by Connection::get_properties
+#[derive(Debug, Copy, Clone)]
+pub struct ConnectionProperties {
+    pub priority: u32, // Lower values have higher priority
+    pub timeout_for_aborting: Option<Duration>, // None to never abort
+    pub keepalive_timeout: Option<Duration>, // None to disable keepalives
+    pub capacity_profile: CapacityProfile,
+    pub multipath_policy: MultipathPolicy,
+    pub min_send_rate: Option<u64>,
+    pub min_recv_rate: Option<u64>,
+    pub max_send_rate: Option<u64>,
+    pub max_recv_rate: Option<u64>,
+    pub group_transmission_scheduler: SchedulerType,
+
+    // Read-only properties
+    pub max_message_size_send: Option<usize>, // None if message size is not limited
+    pub max_message_size_recv: Option<usize>,
+    pub state: ConnectionState,
+}
+
+impl Default for ConnectionProperties {
+    fn default() -> ConnectionProperties {
+        ConnectionProperties {
+            priority: 100,
+            timeout_for_aborting: None,
+            keepalive_timeout: None,
+            capacity_profile: CapacityProfile::Default,
+            multipath_policy: MultipathPolicy::Handover,
+            min_send_rate: None,
+            min_recv_rate: None,
+            max_send_rate: None,
+            max_recv_rate: None,
+            group_transmission_scheduler: SchedulerType::WeightedFairQueueing,
+            max_message_size_send: None,
+            max_message_size_recv: None,
+            state: ConnectionState::Establishing,
+        }
+    }
+}
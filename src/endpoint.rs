@@ -1,12 +1,12 @@
-#[derive(Debug, Clone, Copy)]
-pub struct LocalEndpoint<'a> {
+#[derive(Debug, Clone)]
+pub struct LocalEndpoint {
     pub port: Option<u16>,
-    pub address: Option<&'a str>,
-    pub interface: Option<&'a str>,
+    pub address: Option<String>,
+    pub interface: Option<String>,
 }
 
-impl<'a> LocalEndpoint<'a> {
-    pub fn new() -> LocalEndpoint<'a> {
+impl LocalEndpoint {
+    pub fn new() -> LocalEndpoint {
         LocalEndpoint {
             port: None,
             address: None,
@@ -18,24 +18,24 @@ impl<'a> LocalEndpoint<'a> {
         self.port = Some(port);
     }
 
-    pub fn with_address(&mut self, address: &'a str) -> () {
-        self.address = Some(address);
+    pub fn with_address(&mut self, address: &str) -> () {
+        self.address = Some(address.to_string());
     }
 
-    pub fn with_interface(&mut self, interface: &'a str) -> () {
-        self.interface = Some(interface);
+    pub fn with_interface(&mut self, interface: &str) -> () {
+        self.interface = Some(interface.to_string());
     }
 }
 
-#[derive(Debug, Clone, Copy)]
-pub struct RemoteEndpoint<'a> {
+#[derive(Debug, Clone)]
+pub struct RemoteEndpoint {
     pub port: Option<u16>,
-    pub address: Option<&'a str>,
-    pub host_name: Option<&'a str>,
+    pub address: Option<String>,
+    pub host_name: Option<String>,
 }
 
-impl<'a> RemoteEndpoint<'a> {
-    pub fn new() -> RemoteEndpoint<'a> {
+impl RemoteEndpoint {
+    pub fn new() -> RemoteEndpoint {
         RemoteEndpoint {
             port: None,
             address: None,
@@ -47,11 +47,11 @@ impl<'a> RemoteEndpoint<'a> {
         self.port = Some(port);
     }
 
-    pub fn with_address(&mut self, address: &'a str) -> () {
-        self.address = Some(address);
+    pub fn with_address(&mut self, address: &str) -> () {
+        self.address = Some(address.to_string());
     }
 
-    pub fn with_host_name(&mut self, host_name: &'a str) -> () {
-        self.host_name = Some(host_name);
+    pub fn with_host_name(&mut self, host_name: &str) -> () {
+        self.host_name = Some(host_name.to_string());
     }
 }
\ No newline at end of file
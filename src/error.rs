@@ -7,6 +7,7 @@ pub enum TapsError {
     Io(io::Error),
     RemoteEndpointNotProvided,
     LocalEndpointNotProvided,
+    LocalEndpointPortNotProvided,
     RemoteEndpointPortNotProvided,
     RemoteEndpointAddressAndHostNameBothNotProvided,
     NoCompatibleProtocolStacks,
@@ -15,6 +16,19 @@ pub enum TapsError {
     NoCandidateSucceeded,
     MessageSendFailed,
     MessageReceiveFailed,
+    MessageExpired,
+    MessageParseFailed(String),
+    MessageFramingFailed(String),
+    MessageTooLarge,
+    MessagePropertyNotSupported,
+    ConnectionPropertyNotSupported,
+    ConnectionCloseFailed,
+    InvalidSecurityParameters,
+    InvalidAddressRange,
+    InvalidDelimiter,
+    ConnectionAborted,
+    ConnectionClosed,
+    IcmpError,
 }
 
 impl fmt::Display for TapsError {
@@ -26,6 +40,8 @@ impl fmt::Display for TapsError {
                                                                                     Connection initiation to take place."),
             TapsError::LocalEndpointNotProvided                        => write!(f, "No local endpoint was provided. \
                                                                                      A local endpoint must be supplied to listen for incoming Connections."),
+            TapsError::LocalEndpointPortNotProvided                    => write!(f, "No port was provided in a local endpoint. \
+                                                                                     A port must be provided in every local endpoint to listen for incoming Connections."),
             TapsError::RemoteEndpointPortNotProvided                   => write!(f, "No port was provided in the remote endpoint. \
                                                                                     A port must be provided in the remote endpoint for \
                                                                                     Connection initiation to take place."),
@@ -42,6 +58,19 @@ impl fmt::Display for TapsError {
                                                                                      Therefore, Connection ititiation was unsuccessful"),
             TapsError::MessageSendFailed                               => write!(f, "Error sending message"),
             TapsError::MessageReceiveFailed                            => write!(f, "Error receiving message"),
+            TapsError::MessageExpired                                  => write!(f, "Message could not be sent before its lifetime expired"),
+            TapsError::MessageParseFailed(ref reason)                  => write!(f, "Received data could not be parsed as a message: {}", reason),
+            TapsError::MessageFramingFailed(ref reason)                => write!(f, "Message could not be framed for sending: {}", reason),
+            TapsError::MessageTooLarge                                 => write!(f, "A received message is larger than the maximum message size"),
+            TapsError::ConnectionPropertyNotSupported                  => write!(f, "The connection property cannot be set for the protocol stack of the Connection"),
+            TapsError::MessagePropertyNotSupported                     => write!(f, "A property of the message cannot be honoured by the protocol stack of the Connection"),
+            TapsError::ConnectionCloseFailed                           => write!(f, "Error closing connection"),
+            TapsError::ConnectionAborted                               => write!(f, "The connection was aborted due to a transport error."),
+            TapsError::ConnectionClosed                                => write!(f, "The connection has been closed by the remote endpoint."),
+            TapsError::IcmpError                                       => write!(f, "An ICMP error was received for the connection."),
+            TapsError::InvalidSecurityParameters                       => write!(f, "The certificates or private key provided in the security parameters could not be loaded."),
+            TapsError::InvalidAddressRange                             => write!(f, "The address range is not a valid IP address with an optional prefix length."),
+            TapsError::InvalidDelimiter                                => write!(f, "The delimiter of a DelimiterFramer must not be empty."),
         }
     }
 }
@@ -1,21 +1,611 @@
+use crate::error::TapsError;
 use crate::message::Message;
 
-use http::{Request, Response, StatusCode, Version};
+use std::collections::VecDeque;
+use std::sync::Arc;
 
+use http::{header, request, response, HeaderMap, Request, Response, StatusCode, Version};
+
+// A Framer describes how messages are framed on Connections. Framing state is kept separately for
+// each Connection by a FramerInstance, which is created when the Connection is established.
 pub trait Framer<T, U> {
-    fn new_sent_message(&self, message: Message<T>) -> Vec<u8>;
-    fn handle_received_data(&self, received_data: Vec<u8>) -> U;
+    fn new_instance(&self) -> Box<dyn FramerInstance<T, U> + Send + Sync>;
+}
+
+pub trait FramerInstance<T, U> {
+    fn new_sent_message(&mut self, message: Message<T>) -> Result<Vec<u8>, TapsError>;
+
+    // Parse data received on the Connection, which may contain part of a message or several messages.
+    // Data not yet forming a complete message is kept by the instance until more data is received.
+    fn handle_received_data(&mut self, received_data: &[u8]) -> Result<FramerResult<U>, TapsError>;
+
+    // Called once the remote endpoint has finished sending, returning any message delimited by the end of the data.
+    // Called again after each message is returned, so must eventually return None.
+    fn handle_end_of_data(&mut self) -> Result<Option<U>, TapsError> {
+        return Ok(None);
+    }
+
+    // Whether the framer finds where messages end. Otherwise received data is delivered as it arrives,
+    // and on a stream each message is only part of what the remote endpoint sent.
+    fn delimits_messages(&self) -> bool {
+        return true;
+    }
+
+    // Remove the data received of a message which was rejected as larger than the maximum message size,
+    // so that it can be delivered as a partial message.
+    fn take_partial_data(&mut self) -> Vec<u8> {
+        return vec![];
+    }
+}
+
+pub enum FramerResult<U> {
+    // Complete messages parsed from the data received so far
+    Messages(Vec<U>),
+    // No complete message has been received yet
+    NeedMoreData,
+}
+
+// Passes data through unchanged, so that each chunk of data read from the transport is received as a message.
+// Each UDP datagram or chunk of a QUIC stream is a message, and a TCP message is whatever bytes have arrived.
+pub struct RawFramer;
+
+struct RawFramerInstance;
+
+impl Framer<Vec<u8>, Vec<u8>> for RawFramer {
+    fn new_instance(&self) -> Box<dyn FramerInstance<Vec<u8>, Vec<u8>> + Send + Sync> {
+        return Box::new(RawFramerInstance);
+    }
 }
 
-pub struct HttpClientFramer;
+impl FramerInstance<Vec<u8>, Vec<u8>> for RawFramerInstance {
+    fn new_sent_message(&mut self, message: Message<Vec<u8>>) -> Result<Vec<u8>, TapsError> {
+        return Ok(message.data);
+    }
+
+    fn handle_received_data(&mut self, received_data: &[u8]) -> Result<FramerResult<Vec<u8>>, TapsError> {
+        return Ok(FramerResult::Messages(vec![received_data.to_vec()]));
+    }
+
+    fn delimits_messages(&self) -> bool {
+        return false;
+    }
+}
+
+// A stack of framers, where the framer at the top converts the application's messages to bytes, and each
+// framer beneath it frames the byte messages of the layer above, for example to compress or encapsulate them.
+pub struct FramerStack<T, U> {
+    top: Arc<dyn Framer<T, U> + Send + Sync>,
+    layers: Vec<Arc<dyn Framer<Vec<u8>, Vec<u8>> + Send + Sync>>,
+}
+
+struct FramerStackInstance<T, U> {
+    top: Box<dyn FramerInstance<T, U> + Send + Sync>,
+    // Ordered from the layer beneath the top framer to the layer nearest the transport
+    layers: Vec<Box<dyn FramerInstance<Vec<u8>, Vec<u8>> + Send + Sync>>,
+    // Messages parsed once the remote endpoint finished sending, which have not yet been returned
+    final_messages: VecDeque<U>,
+}
+
+impl<T, U> FramerStack<T, U> {
+    pub fn new(top: Arc<dyn Framer<T, U> + Send + Sync>) -> FramerStack<T, U> {
+        FramerStack {
+            top: top,
+            layers: vec![],
+        }
+    }
+
+    // Add a framer beneath the framers already in the stack
+    pub fn with_layer(&mut self, framer: Arc<dyn Framer<Vec<u8>, Vec<u8>> + Send + Sync>) -> () {
+        self.layers.push(framer);
+    }
+}
 
-impl<T: Encode, U: Decode> Framer<T, U> for HttpClientFramer {
-    fn new_sent_message(&self, message: Message<T>) -> Vec<u8> {
-        return message.data.encode();
+impl<T: 'static, U: Send + Sync + 'static> Framer<T, U> for FramerStack<T, U> {
+    fn new_instance(&self) -> Box<dyn FramerInstance<T, U> + Send + Sync> {
+        return Box::new(FramerStackInstance {
+            top: self.top.new_instance(),
+            layers: self.layers.iter().map(|layer| layer.new_instance()).collect(),
+            final_messages: VecDeque::new(),
+        });
     }
+}
 
-    fn handle_received_data(&self, received_data: Vec<u8>) -> U {
-        return U::decode(received_data);
+impl<T, U> FramerStackInstance<T, U> {
+    // Pass data received from the transport up through the layers beneath the top framer
+    fn parse_layers(&mut self, received_data: Vec<Vec<u8>>, end_of_data: bool) -> Result<Vec<Vec<u8>>, TapsError> {
+        let mut data = received_data;
+
+        for layer in self.layers.iter_mut().rev() {
+            let mut parsed = vec![];
+            for chunk in data {
+                if let FramerResult::Messages(messages) = layer.handle_received_data(&chunk)? {
+                    parsed.extend(messages);
+                }
+            }
+            if end_of_data {
+                while let Some(message) = layer.handle_end_of_data()? {
+                    parsed.push(message);
+                }
+            }
+            data = parsed;
+        }
+
+        return Ok(data);
+    }
+}
+
+impl<T, U> FramerInstance<T, U> for FramerStackInstance<T, U> {
+    fn new_sent_message(&mut self, message: Message<T>) -> Result<Vec<u8>, TapsError> {
+        let message_context = message.message_context();
+        let mut message_bytes = self.top.new_sent_message(message)?;
+
+        for layer in self.layers.iter_mut() {
+            message_bytes = layer.new_sent_message(Message::new(message_bytes, message_context))?;
+        }
+        return Ok(message_bytes);
+    }
+
+    fn handle_received_data(&mut self, received_data: &[u8]) -> Result<FramerResult<U>, TapsError> {
+        let mut messages = vec![];
+
+        for chunk in self.parse_layers(vec![received_data.to_vec()], false)? {
+            if let FramerResult::Messages(parsed) = self.top.handle_received_data(&chunk)? {
+                messages.extend(parsed);
+            }
+        }
+
+        if messages.is_empty() {
+            return Ok(FramerResult::NeedMoreData);
+        }
+        return Ok(FramerResult::Messages(messages));
+    }
+
+    fn handle_end_of_data(&mut self) -> Result<Option<U>, TapsError> {
+        if self.final_messages.is_empty() {
+            for chunk in self.parse_layers(vec![], true)? {
+                if let FramerResult::Messages(parsed) = self.top.handle_received_data(&chunk)? {
+                    self.final_messages.extend(parsed);
+                }
+            }
+            while let Some(message) = self.top.handle_end_of_data()? {
+                self.final_messages.push_back(message);
+            }
+        }
+
+        return Ok(self.final_messages.pop_front());
+    }
+
+    fn delimits_messages(&self) -> bool {
+        return self.top.delimits_messages() || self.layers.iter().any(|layer| layer.delimits_messages());
+    }
+
+    // Partial data is taken from the layer nearest the transport which holds any
+    fn take_partial_data(&mut self) -> Vec<u8> {
+        for layer in self.layers.iter_mut().rev() {
+            let data = layer.take_partial_data();
+            if !data.is_empty() {
+                return data;
+            }
+        }
+        return self.top.take_partial_data();
+    }
+}
+
+// HTTP/1.1 client framer. Requests are sent in full, and responses are parsed in the order their
+// requests were sent, so requests can be pipelined over a persistent Connection.
+#[derive(Debug, Clone, Copy)]
+pub struct HttpClientFramer {
+    pub max_message_size: usize,
+}
+
+struct HttpClientFramerInstance {
+    framer: HttpClientFramer,
+    buffer: Vec<u8>,
+    pending_requests: PendingRequests,
+}
+
+impl HttpClientFramer {
+    pub fn new() -> HttpClientFramer {
+        HttpClientFramer {
+            max_message_size: 64 * 1024 * 1024,
+        }
+    }
+
+    pub fn with_max_message_size(&mut self, max_message_size: usize) -> () {
+        self.max_message_size = max_message_size;
+    }
+}
+
+impl<T: Encode + 'static, U: Decode + 'static> Framer<T, U> for HttpClientFramer {
+    fn new_instance(&self) -> Box<dyn FramerInstance<T, U> + Send + Sync> {
+        return Box::new(HttpClientFramerInstance {
+            framer: *self,
+            buffer: Vec::new(),
+            pending_requests: PendingRequests::new(),
+        });
+    }
+}
+
+impl<T: Encode, U: Decode> FramerInstance<T, U> for HttpClientFramerInstance {
+    fn new_sent_message(&mut self, message: Message<T>) -> Result<Vec<u8>, TapsError> {
+        let request_bytes = message.data.encode();
+        self.pending_requests.push(&request_bytes);
+        return Ok(request_bytes);
+    }
+
+    fn handle_received_data(&mut self, received_data: &[u8]) -> Result<FramerResult<U>, TapsError> {
+        self.buffer.extend_from_slice(received_data);
+
+        let mut messages = vec![];
+
+        while let Some((header_end, status_code, body_length)) = response_head(&self.buffer)? {
+            let body_length = if self.pending_requests.is_head_response(status_code) { BodyLength::Empty } else { body_length };
+
+            // Incomplete, or delimited by the server closing the connection
+            let len = match message_length(&self.buffer, header_end, body_length, self.framer.max_message_size)? {
+                Some(len) => len,
+                None => break,
+            };
+
+            self.pending_requests.response_complete(status_code);
+
+            let message_data: Vec<u8> = self.buffer.drain(..len).collect();
+            messages.push(U::decode(message_data)?);
+        }
+
+        // The rest of the buffer is part of a single response, such as one delimited by the server closing the connection
+        if self.buffer.len() > self.framer.max_message_size {
+            return Err(TapsError::MessageTooLarge);
+        }
+
+        if messages.is_empty() {
+            return Ok(FramerResult::NeedMoreData);
+        }
+        return Ok(FramerResult::Messages(messages));
+    }
+
+    fn handle_end_of_data(&mut self) -> Result<Option<U>, TapsError> {
+        if self.buffer.is_empty() {
+            return Ok(None);
+        }
+
+        let message_data = self.buffer.split_off(0);
+        match response_head(&message_data)? {
+            Some((_, status_code, BodyLength::UntilClose)) => {
+                self.pending_requests.response_complete(status_code);
+                return Ok(Some(U::decode(message_data)?));
+            },
+            _ => return Err(TapsError::MessageParseFailed("HTTP response was truncated".to_string())),
+        }
+    }
+
+    fn take_partial_data(&mut self) -> Vec<u8> {
+        return self.buffer.split_off(0);
+    }
+}
+
+// HTTP/1.1 server framer, for use with a Listener. Requests are parsed as they arrive, and responses
+// must be sent in the order the requests were received. Requests larger than the maximum message size
+// are rejected without buffering their bodies.
+#[derive(Debug, Clone, Copy)]
+pub struct HttpServerFramer {
+    pub max_message_size: usize,
+}
+
+struct HttpServerFramerInstance {
+    framer: HttpServerFramer,
+    buffer: Vec<u8>,
+    pending_requests: PendingRequests,
+}
+
+impl HttpServerFramer {
+    pub fn new() -> HttpServerFramer {
+        HttpServerFramer {
+            max_message_size: 16 * 1024 * 1024,
+        }
+    }
+
+    pub fn with_max_message_size(&mut self, max_message_size: usize) -> () {
+        self.max_message_size = max_message_size;
+    }
+}
+
+impl<T: Encode + 'static, U: Decode + 'static> Framer<T, U> for HttpServerFramer {
+    fn new_instance(&self) -> Box<dyn FramerInstance<T, U> + Send + Sync> {
+        return Box::new(HttpServerFramerInstance {
+            framer: *self,
+            buffer: Vec::new(),
+            pending_requests: PendingRequests::new(),
+        });
+    }
+}
+
+impl<T: Encode, U: Decode> FramerInstance<T, U> for HttpServerFramerInstance {
+    fn new_sent_message(&mut self, message: Message<T>) -> Result<Vec<u8>, TapsError> {
+        let mut response_bytes = message.data.encode();
+
+        let (header_end, status_code, _) = match response_head(&response_bytes)? {
+            Some(head) => head,
+            None => return Err(TapsError::MessageSendFailed),
+        };
+
+        if self.pending_requests.is_head_response(status_code) {
+            response_bytes.truncate(header_end);
+        }
+        self.pending_requests.response_complete(status_code);
+        return Ok(response_bytes);
+    }
+
+    fn handle_received_data(&mut self, received_data: &[u8]) -> Result<FramerResult<U>, TapsError> {
+        self.buffer.extend_from_slice(received_data);
+
+        let mut messages = vec![];
+
+        while let Some((header_end, body_length)) = request_head(&self.buffer)? {
+            let len = match message_length(&self.buffer, header_end, body_length, self.framer.max_message_size)? {
+                Some(len) => len,
+                None => break,
+            };
+
+            let message_data: Vec<u8> = self.buffer.drain(..len).collect();
+            self.pending_requests.push(&message_data);
+            messages.push(U::decode(message_data)?);
+        }
+
+        // The rest of the buffer is part of a single request
+        if self.buffer.len() > self.framer.max_message_size {
+            return Err(TapsError::MessageTooLarge);
+        }
+
+        if messages.is_empty() {
+            return Ok(FramerResult::NeedMoreData);
+        }
+        return Ok(FramerResult::Messages(messages));
+    }
+
+    fn handle_end_of_data(&mut self) -> Result<Option<U>, TapsError> {
+        // Requests are never delimited by the client closing the connection
+        if self.buffer.is_empty() {
+            return Ok(None);
+        }
+        self.buffer.clear();
+        return Err(TapsError::MessageParseFailed("HTTP request was truncated".to_string()));
+    }
+
+    fn take_partial_data(&mut self) -> Vec<u8> {
+        return self.buffer.split_off(0);
+    }
+}
+
+// The requests on a connection which are awaiting responses, in the order they were made, so that each
+// response can be matched with its request
+struct PendingRequests {
+    // For each request, whether it was a HEAD request
+    head_requests: VecDeque<bool>,
+}
+
+impl PendingRequests {
+    fn new() -> PendingRequests {
+        PendingRequests {
+            head_requests: VecDeque::new(),
+        }
+    }
+
+    fn push(&mut self, request_bytes: &[u8]) {
+        self.head_requests.push_back(request_bytes.starts_with(b"HEAD "));
+    }
+
+    // Responses to HEAD requests describe the body which would have been sent, but have none
+    fn is_head_response(&self, status_code: u16) -> bool {
+        return !is_interim(status_code) && self.head_requests.front() == Some(&true);
+    }
+
+    // A final response completes the oldest request
+    fn response_complete(&mut self, status_code: u16) {
+        if !is_interim(status_code) {
+            self.head_requests.pop_front();
+        }
+    }
+}
+
+// Interim responses precede the final response to a request
+fn is_interim(status_code: u16) -> bool {
+    return (100..200).contains(&status_code) && status_code != 101;
+}
+
+// How the end of the body of an HTTP/1.1 message is found
+enum BodyLength {
+    Empty,
+    ContentLength(usize),
+    Chunked,
+    UntilClose,
+}
+
+// Parse the head of the HTTP/1.1 response at the start of the data, if it has been received in full,
+// returning the length of the head, the status code and how the length of the body is determined
+fn response_head(data: &[u8]) -> Result<Option<(usize, u16, BodyLength)>, TapsError> {
+    let header_end = match find(data, b"\r\n\r\n") {
+        Some(pos) => pos + 4,
+        None => return Ok(None),
+    };
+
+    let head = parse_str(&data[..header_end])?;
+    let mut lines = head.lines();
+
+    let status_code = lines.next()
+        .and_then(|status_line| status_line.split_whitespace().nth(1))
+        .and_then(|status_code| status_code.parse::<u16>().ok())
+        .ok_or_else(|| TapsError::MessageParseFailed("Invalid HTTP status line".to_string()))?;
+
+    // Responses which never have a body
+    if (100..200).contains(&status_code) || status_code == 204 || status_code == 304 {
+        return Ok(Some((header_end, status_code, BodyLength::Empty)));
+    }
+
+    // A response without a length is delimited by the server closing the connection
+    let body_length = headers_body_length(lines)?.unwrap_or(BodyLength::UntilClose);
+    return Ok(Some((header_end, status_code, body_length)));
+}
+
+// Parse the head of the HTTP/1.1 request at the start of the data, if it has been received in full,
+// returning the length of the head and how the length of the body is determined
+fn request_head(data: &[u8]) -> Result<Option<(usize, BodyLength)>, TapsError> {
+    let header_end = match find(data, b"\r\n\r\n") {
+        Some(pos) => pos + 4,
+        None => return Ok(None),
+    };
+
+    let head = parse_str(&data[..header_end])?;
+    let mut lines = head.lines();
+
+    if lines.next().map(|request_line| request_line.split_whitespace().count()) != Some(3) {
+        return Err(TapsError::MessageParseFailed("Invalid HTTP request line".to_string()));
+    }
+
+    // A request without a length has no body
+    let body_length = headers_body_length(lines)?.unwrap_or(BodyLength::Empty);
+    return Ok(Some((header_end, body_length)));
+}
+
+// How the length of the body is given by the header fields of a message, if at all
+fn headers_body_length<'a>(lines: impl Iterator<Item = &'a str>) -> Result<Option<BodyLength>, TapsError> {
+    let mut body_length = None;
+
+    for header in lines.take_while(|header| !header.is_empty()) {
+        let (name, value) = parse_header(header)?;
+
+        if name.eq_ignore_ascii_case("Transfer-Encoding") && value.to_ascii_lowercase().contains("chunked") {
+            body_length = Some(BodyLength::Chunked);
+        } else if name.eq_ignore_ascii_case("Content-Length") {
+            match value.parse::<usize>() {
+                // Transfer-Encoding takes precedence over Content-Length
+                Ok(content_length) => if body_length.is_none() {
+                    body_length = Some(BodyLength::ContentLength(content_length));
+                },
+                Err(_) => return Err(TapsError::MessageParseFailed("Invalid HTTP Content-Length".to_string())),
+            }
+        }
+    }
+
+    return Ok(body_length);
+}
+
+// Length of the message at the start of the data, if it has been received in full. A message which
+// is larger than the maximum message size is rejected as soon as its length is known.
+fn message_length(data: &[u8], header_end: usize, body_length: BodyLength, max_message_size: usize) -> Result<Option<usize>, TapsError> {
+    let len = match body_length {
+        BodyLength::Empty => header_end,
+        BodyLength::ContentLength(content_length) => match header_end.checked_add(content_length) {
+            Some(len) if len <= max_message_size => len,
+            _ => return Err(TapsError::MessageTooLarge),
+        },
+        BodyLength::Chunked => match chunked_body_length(&data[header_end..])? {
+            Some(body_len) => header_end + body_len,
+            None => return Ok(None),
+        },
+        BodyLength::UntilClose => return Ok(None),
+    };
+
+    if len > max_message_size {
+        return Err(TapsError::MessageTooLarge);
+    }
+    if data.len() < len {
+        return Ok(None);
+    }
+    return Ok(Some(len));
+}
+
+// Length of a chunked body, including its trailer section, if it has been received in full
+fn chunked_body_length(data: &[u8]) -> Result<Option<usize>, TapsError> {
+    let mut pos = 0;
+
+    loop {
+        let (size, line_end) = match chunk_size(&data[pos..])? {
+            Some((size, line_len)) => (size, pos + line_len),
+            None => return Ok(None),
+        };
+        pos = line_end;
+
+        if size == 0 {
+            // Skip trailer fields up to the empty line ending the body
+            loop {
+                let line_end = match find(&data[pos..], b"\r\n") {
+                    Some(len) => pos + len,
+                    None => return Ok(None),
+                };
+                let empty = line_end == pos;
+                pos = line_end + 2;
+                if empty {
+                    return Ok(Some(pos));
+                }
+            }
+        }
+
+        // Chunk data is followed by CRLF
+        pos = match pos.checked_add(size).and_then(|chunk_end| chunk_end.checked_add(2)) {
+            Some(pos) => pos,
+            None => return Err(TapsError::MessageParseFailed("Invalid HTTP chunk size".to_string())),
+        };
+        if data.len() < pos {
+            return Ok(None);
+        }
+    }
+}
+
+// Remove the chunked transfer coding from a body which has been received in full
+fn dechunk(data: &[u8]) -> Result<Vec<u8>, TapsError> {
+    let mut body = Vec::new();
+    let mut pos = 0;
+
+    while let Some((size, line_len)) = chunk_size(data.get(pos..).unwrap_or(&[]))? {
+        pos += line_len;
+        let chunk_end = match pos.checked_add(size) {
+            Some(chunk_end) => chunk_end,
+            None => return Err(TapsError::MessageParseFailed("Invalid HTTP chunk size".to_string())),
+        };
+        if size == 0 || data.len() < chunk_end {
+            break;
+        }
+        body.extend_from_slice(&data[pos..chunk_end]);
+        pos = chunk_end + 2;
+    }
+
+    return Ok(body);
+}
+
+// Parse the size line at the start of a chunk, returning the chunk size and the length of the line
+fn chunk_size(data: &[u8]) -> Result<Option<(usize, usize)>, TapsError> {
+    let line_end = match find(data, b"\r\n") {
+        Some(line_end) => line_end,
+        None => return Ok(None),
+    };
+
+    // Chunk extensions are ignored
+    let size_line = parse_str(&data[..line_end])?;
+    let size_str = size_line.split(';').next().unwrap_or("").trim();
+
+    match usize::from_str_radix(size_str, 16) {
+        Ok(size) => return Ok(Some((size, line_end + 2))),
+        Err(_) => return Err(TapsError::MessageParseFailed("Invalid HTTP chunk size".to_string())),
+    }
+}
+
+fn find(data: &[u8], pattern: &[u8]) -> Option<usize> {
+    return data.windows(pattern.len()).position(|window| window == pattern);
+}
+
+fn parse_str(data: &[u8]) -> Result<&str, TapsError> {
+    match std::str::from_utf8(data) {
+        Ok(s) => return Ok(s),
+        Err(_) => return Err(TapsError::MessageParseFailed("HTTP message head is not valid UTF-8".to_string())),
+    }
+}
+
+fn parse_header(header: &str) -> Result<(&str, &str), TapsError> {
+    let mut split = header.splitn(2, ':');
+    match (split.next(), split.next()) {
+        (Some(name), Some(value)) => return Ok((name.trim(), value.trim())),
+        _ => return Err(TapsError::MessageParseFailed(format!("Invalid HTTP header: {}", header))),
     }
 }
 
@@ -25,53 +615,877 @@ pub trait Encode {
 
 impl Encode for Request<()> {
     fn encode(self) -> Vec<u8> {
-        let mut request_bytes = Vec::new();
         let (parts, _body) = self.into_parts();
+        return encode_request(parts, &[]);
+    }
+}
 
-        request_bytes.extend_from_slice(parts.method.as_str().as_bytes());
-        request_bytes.extend_from_slice(b" / HTTP/1.1\r\n");
-        request_bytes.extend_from_slice(b"\r\nHost: ");
-        request_bytes.extend_from_slice(format!("{}", parts.uri).as_bytes());
-        request_bytes.extend_from_slice(b"\r\n\r\n");
-        return request_bytes;
+impl Encode for Request<Vec<u8>> {
+    fn encode(self) -> Vec<u8> {
+        let (parts, body) = self.into_parts();
+        return encode_request(parts, &body);
     }
 }
 
-pub trait Decode {
-    fn decode(data: Vec<u8>) -> Self;
+fn encode_request(parts: request::Parts, body: &[u8]) -> Vec<u8> {
+    let mut request_bytes = Vec::new();
+
+    // Request line, with the path and query of the URI in origin form
+    let path_and_query = parts.uri.path_and_query().map(|p| p.as_str()).unwrap_or("/");
+    let path_and_query = if path_and_query.is_empty() { "/" } else { path_and_query };
+    let version = match parts.version {
+        Version::HTTP_10 => "HTTP/1.0",
+        _ => "HTTP/1.1",
+    };
+    request_bytes.extend_from_slice(format!("{} {} {}\r\n", parts.method, path_and_query, version).as_bytes());
+
+    // Host is required in HTTP/1.1 requests, and is taken from the URI if not given as a header
+    if !parts.headers.contains_key(header::HOST) {
+        if let Some(authority) = parts.uri.authority() {
+            request_bytes.extend_from_slice(format!("Host: {}\r\n", authority).as_bytes());
+        }
+    }
+
+    encode_headers(&mut request_bytes, &parts.headers);
+
+    // The server finds the end of the body from its length
+    if !body.is_empty() && !body_framed(&parts.headers) {
+        request_bytes.extend_from_slice(format!("Content-Length: {}\r\n", body.len()).as_bytes());
+    }
+
+    request_bytes.extend_from_slice(b"\r\n");
+    request_bytes.extend_from_slice(body);
+    return request_bytes;
+}
+
+impl Encode for Response<()> {
+    fn encode(self) -> Vec<u8> {
+        let (parts, _body) = self.into_parts();
+        return encode_response(parts, &[]);
+    }
+}
+
+impl Encode for Response<Vec<u8>> {
+    fn encode(self) -> Vec<u8> {
+        let (parts, body) = self.into_parts();
+        return encode_response(parts, &body);
+    }
+}
+
+fn encode_response(parts: response::Parts, body: &[u8]) -> Vec<u8> {
+    let mut response_bytes = Vec::new();
+
+    let version = match parts.version {
+        Version::HTTP_10 => "HTTP/1.0",
+        _ => "HTTP/1.1",
+    };
+    let reason = parts.status.canonical_reason().unwrap_or("");
+    response_bytes.extend_from_slice(format!("{} {} {}\r\n", version, parts.status.as_str(), reason).as_bytes());
+
+    encode_headers(&mut response_bytes, &parts.headers);
+
+    // The client finds the end of the body from its length, so that the connection can be kept alive
+    let status_code = parts.status.as_u16();
+    let may_have_body = !(100..200).contains(&status_code) && status_code != 204 && status_code != 304;
+    if may_have_body && !body_framed(&parts.headers) {
+        response_bytes.extend_from_slice(format!("Content-Length: {}\r\n", body.len()).as_bytes());
+    }
+
+    response_bytes.extend_from_slice(b"\r\n");
+    response_bytes.extend_from_slice(body);
+    return response_bytes;
+}
+
+fn encode_headers(message_bytes: &mut Vec<u8>, headers: &HeaderMap) {
+    for (name, value) in headers.iter() {
+        message_bytes.extend_from_slice(name.as_str().as_bytes());
+        message_bytes.extend_from_slice(b": ");
+        message_bytes.extend_from_slice(value.as_bytes());
+        message_bytes.extend_from_slice(b"\r\n");
+    }
+}
+
+fn body_framed(headers: &HeaderMap) -> bool {
+    return headers.contains_key(header::CONTENT_LENGTH) || headers.contains_key(header::TRANSFER_ENCODING);
+}
+
+pub trait Decode: Sized {
+    fn decode(data: Vec<u8>) -> Result<Self, TapsError>;
 }
 
 impl Decode for Response<()> {
-    fn decode(data: Vec<u8>) -> Self {
-        let response_str = std::str::from_utf8(&data).unwrap();
-        let mut response_lines = response_str.lines();
-        let mut status_line = response_lines.next().unwrap().split_whitespace();
-        let version = match status_line.next().unwrap() {
-            "HTTP/0.9" => Version::HTTP_09,
-            "HTTP/1.0" => Version::HTTP_10,
-            "HTTP/1.1" => Version::HTTP_11,
-            "HTTP/2.0" => Version::HTTP_2,
-            "HTTP/3.0" => Version::HTTP_3,
-            _ => Version::HTTP_11,
-        };
-        let status_code = StatusCode::from_u16(status_line.next().unwrap().parse::<u16>().unwrap()).unwrap();
+    fn decode(data: Vec<u8>) -> Result<Self, TapsError> {
+        return Ok(Response::<Vec<u8>>::decode(data)?.map(|_| ()));
+    }
+}
+
+impl Decode for Response<Vec<u8>> {
+    fn decode(data: Vec<u8>) -> Result<Self, TapsError> {
+        let (start_line, headers, body) = decode_message(&data)?;
+
+        let mut status_line = start_line.split_whitespace();
+        let version = parse_version(status_line.next())?;
+        let status_code = status_line.next()
+            .and_then(|status_code| status_code.parse::<u16>().ok())
+            .and_then(|status_code| StatusCode::from_u16(status_code).ok())
+            .ok_or_else(|| TapsError::MessageParseFailed("Invalid HTTP status code".to_string()))?;
 
         let mut response = Response::builder()
             .version(version)
             .status(status_code);
 
-        for header in response_lines {
-            // End of headers
-            if header.trim().is_empty() {
+        for (name, value) in headers {
+            response = response.header(name, value);
+        }
+
+        match response.body(body) {
+            Ok(response) => return Ok(response),
+            Err(e) => return Err(TapsError::MessageParseFailed(e.to_string())),
+        }
+    }
+}
+
+impl Decode for Request<()> {
+    fn decode(data: Vec<u8>) -> Result<Self, TapsError> {
+        return Ok(Request::<Vec<u8>>::decode(data)?.map(|_| ()));
+    }
+}
+
+impl Decode for Request<Vec<u8>> {
+    fn decode(data: Vec<u8>) -> Result<Self, TapsError> {
+        let (start_line, headers, body) = decode_message(&data)?;
+
+        let mut request_line = start_line.split_whitespace();
+        let method = request_line.next().unwrap_or("");
+        let target = request_line.next().unwrap_or("");
+        let version = parse_version(request_line.next())?;
+
+        let mut request = Request::builder()
+            .method(method)
+            .uri(target)
+            .version(version);
+
+        for (name, value) in headers {
+            request = request.header(name, value);
+        }
+
+        match request.body(body) {
+            Ok(request) => return Ok(request),
+            Err(e) => return Err(TapsError::MessageParseFailed(e.to_string())),
+        }
+    }
+}
+
+// Split an HTTP/1.1 message into its start line, header fields and body, removing any chunked transfer coding
+fn decode_message(data: &[u8]) -> Result<(&str, Vec<(&str, &str)>, Vec<u8>), TapsError> {
+    let header_end = find(data, b"\r\n\r\n").map(|pos| pos + 4).unwrap_or(data.len());
+    let head = parse_str(&data[..header_end])?;
+    let mut lines = head.lines();
+
+    let start_line = lines.next().unwrap_or("");
+    let mut headers = vec![];
+    let mut chunked = false;
+
+    for header in lines {
+        // End of headers
+        if header.trim().is_empty() {
+            break;
+        }
+
+        let (name, value) = parse_header(header)?;
+        if name.eq_ignore_ascii_case("Transfer-Encoding") && value.to_ascii_lowercase().contains("chunked") {
+            chunked = true;
+        }
+        headers.push((name, value));
+    }
+
+    let body = if chunked {
+        dechunk(&data[header_end..])?
+    } else {
+        data[header_end..].to_vec()
+    };
+
+    return Ok((start_line, headers, body));
+}
+
+fn parse_version(version: Option<&str>) -> Result<Version, TapsError> {
+    match version {
+        Some("HTTP/0.9") => return Ok(Version::HTTP_09),
+        Some("HTTP/1.0") => return Ok(Version::HTTP_10),
+        Some("HTTP/1.1") => return Ok(Version::HTTP_11),
+        Some("HTTP/2.0") => return Ok(Version::HTTP_2),
+        Some("HTTP/3.0") => return Ok(Version::HTTP_3),
+        _ => return Err(TapsError::MessageParseFailed("Invalid HTTP version".to_string())),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PrefixWidth {
+    U8,
+    U16,
+    U32,
+    U64,
+    // Unsigned LEB128, as used by Protocol Buffers
+    Varint,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Endianness {
+    Big,
+    Little,
+}
+
+// Frames each message with a prefix giving its length. Messages are sent and received as
+// any byte container, such as Vec<u8> or bytes::Bytes.
+#[derive(Debug, Clone, Copy)]
+pub struct LengthPrefixFramer {
+    pub width: PrefixWidth,
+    pub endianness: Endianness,
+    pub max_message_size: usize,
+}
+
+struct LengthPrefixFramerInstance {
+    framer: LengthPrefixFramer,
+    buffer: Vec<u8>,
+}
+
+impl LengthPrefixFramer {
+    pub fn new() -> LengthPrefixFramer {
+        LengthPrefixFramer {
+            width: PrefixWidth::U32,
+            endianness: Endianness::Big,
+            max_message_size: 16 * 1024 * 1024,
+        }
+    }
+
+    pub fn with_width(&mut self, width: PrefixWidth) -> () {
+        self.width = width;
+    }
+
+    pub fn with_endianness(&mut self, endianness: Endianness) -> () {
+        self.endianness = endianness;
+    }
+
+    pub fn with_max_message_size(&mut self, max_message_size: usize) -> () {
+        self.max_message_size = max_message_size;
+    }
+
+    fn encode_prefix(&self, len: usize) -> Result<Vec<u8>, TapsError> {
+        let len = len as u64;
+        let too_large = match self.width {
+            PrefixWidth::U8 => len > u8::MAX as u64,
+            PrefixWidth::U16 => len > u16::MAX as u64,
+            PrefixWidth::U32 => len > u32::MAX as u64,
+            PrefixWidth::U64 | PrefixWidth::Varint => false,
+        };
+        if too_large {
+            return Err(TapsError::MessageFramingFailed("Message length does not fit in the length prefix".to_string()));
+        }
+
+        if self.width == PrefixWidth::Varint {
+            let mut prefix = vec![];
+            let mut remaining = len;
+            loop {
+                let byte = (remaining & 0x7f) as u8;
+                remaining >>= 7;
+                if remaining == 0 {
+                    prefix.push(byte);
+                    return Ok(prefix);
+                }
+                prefix.push(byte | 0x80);
+            }
+        }
+
+        let width = self.prefix_width();
+        let prefix = match self.endianness {
+            Endianness::Big => len.to_be_bytes()[8 - width..].to_vec(),
+            Endianness::Little => len.to_le_bytes()[..width].to_vec(),
+        };
+        return Ok(prefix);
+    }
+
+    // Decode the length prefix at the start of the data, returning the message length and the length of the prefix
+    fn decode_prefix(&self, data: &[u8]) -> Result<Option<(u64, usize)>, TapsError> {
+        if self.width == PrefixWidth::Varint {
+            let mut len: u64 = 0;
+            for (i, byte) in data.iter().enumerate() {
+                if i == 10 {
+                    return Err(TapsError::MessageParseFailed("Varint length prefix is too long".to_string()));
+                }
+                len |= ((byte & 0x7f) as u64) << (7 * i);
+                if byte & 0x80 == 0 {
+                    return Ok(Some((len, i + 1)));
+                }
+            }
+            return Ok(None);
+        }
+
+        let width = self.prefix_width();
+        if data.len() < width {
+            return Ok(None);
+        }
+
+        let mut bytes = [0u8; 8];
+        let len = match self.endianness {
+            Endianness::Big => {
+                bytes[8 - width..].copy_from_slice(&data[..width]);
+                u64::from_be_bytes(bytes)
+            },
+            Endianness::Little => {
+                bytes[..width].copy_from_slice(&data[..width]);
+                u64::from_le_bytes(bytes)
+            },
+        };
+        return Ok(Some((len, width)));
+    }
+
+    fn prefix_width(&self) -> usize {
+        match self.width {
+            PrefixWidth::U8 => return 1,
+            PrefixWidth::U16 => return 2,
+            PrefixWidth::U32 => return 4,
+            PrefixWidth::U64 | PrefixWidth::Varint => return 8,
+        }
+    }
+}
+
+impl<T: AsRef<[u8]> + 'static, U: From<Vec<u8>> + 'static> Framer<T, U> for LengthPrefixFramer {
+    fn new_instance(&self) -> Box<dyn FramerInstance<T, U> + Send + Sync> {
+        return Box::new(LengthPrefixFramerInstance {
+            framer: *self,
+            buffer: Vec::new(),
+        });
+    }
+}
+
+impl<T: AsRef<[u8]>, U: From<Vec<u8>>> FramerInstance<T, U> for LengthPrefixFramerInstance {
+    fn new_sent_message(&mut self, message: Message<T>) -> Result<Vec<u8>, TapsError> {
+        let data = message.data.as_ref();
+        if data.len() > self.framer.max_message_size {
+            return Err(TapsError::MessageFramingFailed("Message is larger than the maximum message size".to_string()));
+        }
+
+        let mut message_bytes = self.framer.encode_prefix(data.len())?;
+        message_bytes.extend_from_slice(data);
+        return Ok(message_bytes);
+    }
+
+    fn handle_received_data(&mut self, received_data: &[u8]) -> Result<FramerResult<U>, TapsError> {
+        self.buffer.extend_from_slice(received_data);
+
+        let mut messages = vec![];
+
+        while let Some((len, prefix_len)) = self.framer.decode_prefix(&self.buffer)? {
+            if len > self.framer.max_message_size as u64 {
+                return Err(TapsError::MessageTooLarge);
+            }
+
+            let message_end = prefix_len + len as usize;
+            if self.buffer.len() < message_end {
                 break;
             }
-            
-            let mut split = header.split(": ");            
-            response = response.header(split.next().unwrap(), split.next().unwrap());
-        }
-        
-        return response
-            .body(())
-            .unwrap();
+
+            let message_data = self.buffer[prefix_len..message_end].to_vec();
+            self.buffer.drain(..message_end);
+            messages.push(U::from(message_data));
+        }
+
+        if messages.is_empty() {
+            return Ok(FramerResult::NeedMoreData);
+        }
+        return Ok(FramerResult::Messages(messages));
+    }
+
+    fn handle_end_of_data(&mut self) -> Result<Option<U>, TapsError> {
+        if self.buffer.is_empty() {
+            return Ok(None);
+        }
+        self.buffer.clear();
+        return Err(TapsError::MessageParseFailed("Length-prefixed message was truncated".to_string()));
+    }
+
+    fn take_partial_data(&mut self) -> Vec<u8> {
+        return self.buffer.split_off(0);
+    }
+}
+
+// Frames each message by ending it with a delimiter, such as a newline. Messages are sent and received
+// as any byte container, such as Vec<u8> or bytes::Bytes, and do not include the delimiter.
+#[derive(Debug, Clone)]
+pub struct DelimiterFramer {
+    // Never empty, as an empty delimiter would end a message at every byte
+    delimiter: Vec<u8>,
+    pub max_line_length: usize,
+}
+
+struct DelimiterFramerInstance {
+    framer: DelimiterFramer,
+    buffer: Vec<u8>,
+}
+
+impl DelimiterFramer {
+    pub fn new(delimiter: &[u8]) -> Result<DelimiterFramer, TapsError> {
+        if delimiter.is_empty() {
+            return Err(TapsError::InvalidDelimiter);
+        }
+
+        return Ok(DelimiterFramer {
+            delimiter: delimiter.to_vec(),
+            max_line_length: 64 * 1024,
+        });
+    }
+
+    pub fn with_max_line_length(&mut self, max_line_length: usize) -> () {
+        self.max_line_length = max_line_length;
+    }
+}
+
+impl<T: AsRef<[u8]> + 'static, U: From<Vec<u8>> + 'static> Framer<T, U> for DelimiterFramer {
+    fn new_instance(&self) -> Box<dyn FramerInstance<T, U> + Send + Sync> {
+        return Box::new(DelimiterFramerInstance {
+            framer: self.clone(),
+            buffer: Vec::new(),
+        });
+    }
+}
+
+impl<T: AsRef<[u8]>, U: From<Vec<u8>>> FramerInstance<T, U> for DelimiterFramerInstance {
+    fn new_sent_message(&mut self, message: Message<T>) -> Result<Vec<u8>, TapsError> {
+        let data = message.data.as_ref();
+        if data.len() > self.framer.max_line_length {
+            return Err(TapsError::MessageFramingFailed("Message is longer than the maximum line length".to_string()));
+        }
+        if find(data, &self.framer.delimiter).is_some() {
+            return Err(TapsError::MessageFramingFailed("Message contains the delimiter".to_string()));
+        }
+
+        let mut message_bytes = data.to_vec();
+        message_bytes.extend_from_slice(&self.framer.delimiter);
+        return Ok(message_bytes);
+    }
+
+    fn handle_received_data(&mut self, received_data: &[u8]) -> Result<FramerResult<U>, TapsError> {
+        self.buffer.extend_from_slice(received_data);
+
+        let mut messages = vec![];
+
+        while let Some(line_len) = find(&self.buffer, &self.framer.delimiter) {
+            if line_len > self.framer.max_line_length {
+                return Err(TapsError::MessageTooLarge);
+            }
+
+            let message_data = self.buffer[..line_len].to_vec();
+            self.buffer.drain(..line_len + self.framer.delimiter.len());
+            messages.push(U::from(message_data));
+        }
+
+        // A line which has not ended within the maximum length never will
+        if self.buffer.len() > self.framer.max_line_length + self.framer.delimiter.len() {
+            return Err(TapsError::MessageTooLarge);
+        }
+
+        if messages.is_empty() {
+            return Ok(FramerResult::NeedMoreData);
+        }
+        return Ok(FramerResult::Messages(messages));
+    }
+
+    fn handle_end_of_data(&mut self) -> Result<Option<U>, TapsError> {
+        // A final line need not be followed by the delimiter
+        if self.buffer.is_empty() {
+            return Ok(None);
+        }
+        return Ok(Some(U::from(self.buffer.split_off(0))));
+    }
+
+    fn take_partial_data(&mut self) -> Vec<u8> {
+        return self.buffer.split_off(0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type HttpClientInstance = Box<dyn FramerInstance<Request<()>, Response<Vec<u8>>> + Send + Sync>;
+
+    fn http_client_instance() -> HttpClientInstance {
+        return Framer::<Request<()>, Response<Vec<u8>>>::new_instance(&HttpClientFramer::new());
+    }
+
+    fn small_http_client_instance() -> HttpClientInstance {
+        let mut framer = HttpClientFramer::new();
+        framer.with_max_message_size(64);
+        return Framer::<Request<()>, Response<Vec<u8>>>::new_instance(&framer);
+    }
+
+    type HttpServerInstance = Box<dyn FramerInstance<Response<()>, Request<Vec<u8>>> + Send + Sync>;
+
+    fn small_http_server_instance() -> HttpServerInstance {
+        let mut framer = HttpServerFramer::new();
+        framer.with_max_message_size(64);
+        return Framer::<Response<()>, Request<Vec<u8>>>::new_instance(&framer);
+    }
+
+    fn send_request(instance: &mut HttpClientInstance, method: &str) {
+        let request = Request::builder().method(method).uri("http://example.com/").body(()).unwrap();
+        instance.new_sent_message(Message::new(request, None)).unwrap();
+    }
+
+    fn messages<U>(result: Result<FramerResult<U>, TapsError>) -> Vec<U> {
+        match result {
+            Ok(FramerResult::Messages(messages)) => return messages,
+            Ok(FramerResult::NeedMoreData) => panic!("no complete message"),
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    // Frame the messages with one instance, and check that another parses them back whether the data
+    // arrives one byte at a time or all at once
+    fn round_trip(framer: &dyn Framer<Vec<u8>, Vec<u8>>, sent_messages: &[Vec<u8>]) {
+        let mut sender = framer.new_instance();
+        let mut data = vec![];
+        for message in sent_messages {
+            data.extend(sender.new_sent_message(Message::new(message.clone(), None)).unwrap());
+        }
+
+        let mut receiver = framer.new_instance();
+        let mut received_messages = vec![];
+        for byte in data.iter() {
+            if let FramerResult::Messages(messages) = receiver.handle_received_data(&[*byte]).unwrap() {
+                received_messages.extend(messages);
+            }
+        }
+        assert_eq!(received_messages, sent_messages);
+        assert!(matches!(receiver.handle_end_of_data(), Ok(None)));
+
+        let mut receiver = framer.new_instance();
+        assert_eq!(messages(receiver.handle_received_data(&data)), sent_messages);
+    }
+
+    #[test]
+    fn http_client_split_response_test() {
+        let mut instance = http_client_instance();
+        send_request(&mut instance, "GET");
+        send_request(&mut instance, "GET");
+
+        // Neither an incomplete head nor an incomplete body is a message
+        assert!(matches!(instance.handle_received_data(b"HTTP/1.1 200 OK\r\nContent-Len"), Ok(FramerResult::NeedMoreData)));
+        assert!(matches!(instance.handle_received_data(b"gth: 5\r\n\r\nhel"), Ok(FramerResult::NeedMoreData)));
+
+        // The rest of the first response arrives together with the start of the second
+        let responses = messages(instance.handle_received_data(b"loHTTP/1.1 404 Not Found\r\nContent-Length: 2\r\n\r\n"));
+        assert_eq!(responses.len(), 1);
+        assert_eq!(responses[0].status(), StatusCode::OK);
+        assert_eq!(responses[0].body(), b"hello");
+
+        let responses = messages(instance.handle_received_data(b"no"));
+        assert_eq!(responses[0].status(), StatusCode::NOT_FOUND);
+        assert_eq!(responses[0].body(), b"no");
+        assert!(matches!(instance.handle_end_of_data(), Ok(None)));
+    }
+
+    #[test]
+    fn http_client_chunked_response_test() {
+        let mut instance = http_client_instance();
+        send_request(&mut instance, "GET");
+
+        let response = b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n5;ext=1\r\nhello\r\n6\r\n world\r\n0\r\nTrailer: x\r\n\r\n";
+
+        // The body is only complete once the trailer section has ended
+        let (start, end) = response.split_at(response.len() - 2);
+        assert!(matches!(instance.handle_received_data(start), Ok(FramerResult::NeedMoreData)));
+
+        let responses = messages(instance.handle_received_data(end));
+        assert_eq!(responses.len(), 1);
+        assert_eq!(responses[0].body(), b"hello world");
+    }
+
+    #[test]
+    fn http_client_head_response_test() {
+        let mut instance = http_client_instance();
+        send_request(&mut instance, "HEAD");
+        send_request(&mut instance, "GET");
+
+        // The response to a HEAD request has no body, despite its Content-Length
+        let responses = messages(instance.handle_received_data(
+            b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nHTTP/1.1 100 Continue\r\n\r\nHTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nhi"));
+        assert_eq!(responses.len(), 3);
+        assert!(responses[0].body().is_empty());
+        assert_eq!(responses[1].status(), StatusCode::CONTINUE);
+        assert_eq!(responses[2].body(), b"hi");
+    }
+
+    #[test]
+    fn http_client_until_close_response_test() {
+        let mut instance = http_client_instance();
+        send_request(&mut instance, "GET");
+
+        // A response without a length ends when the server closes the connection
+        assert!(matches!(instance.handle_received_data(b"HTTP/1.0 200 OK\r\n\r\nall of it"), Ok(FramerResult::NeedMoreData)));
+        let response = instance.handle_end_of_data().unwrap().unwrap();
+        assert_eq!(response.body(), b"all of it");
+        assert!(matches!(instance.handle_end_of_data(), Ok(None)));
+
+        // A response with a length which is cut short is truncated
+        let mut instance = http_client_instance();
+        send_request(&mut instance, "GET");
+        assert!(matches!(instance.handle_received_data(b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nhi"), Ok(FramerResult::NeedMoreData)));
+        assert!(matches!(instance.handle_end_of_data(), Err(TapsError::MessageParseFailed(_))));
+    }
+
+    #[test]
+    fn http_client_malformed_response_test() {
+        let malformed_responses: [&[u8]; 5] = [
+            b"HTTP/1.1 OK\r\n\r\n",
+            b"HTTP/1.1 abc OK\r\n\r\n",
+            b"HTTP/1.1 200 OK\r\nNo colon\r\n\r\n",
+            b"HTTP/1.1 200 OK\r\nContent-Length: many\r\n\r\n",
+            b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\nxyz\r\n",
+        ];
+
+        for response in malformed_responses.iter() {
+            let mut instance = http_client_instance();
+            send_request(&mut instance, "GET");
+            assert!(matches!(instance.handle_received_data(response), Err(TapsError::MessageParseFailed(_))));
+        }
+    }
+
+    #[test]
+    fn http_client_max_message_size_test() {
+        // A response which would be too large is rejected before its body is received
+        let mut instance = small_http_client_instance();
+        send_request(&mut instance, "GET");
+        assert!(matches!(instance.handle_received_data(b"HTTP/1.1 200 OK\r\nContent-Length: 100\r\n\r\n"), Err(TapsError::MessageTooLarge)));
+
+        // As is a response without a length, or a head, which grows too large
+        let mut instance = small_http_client_instance();
+        send_request(&mut instance, "GET");
+        assert!(matches!(instance.handle_received_data(b"HTTP/1.1 200 OK\r\n\r\n"), Ok(FramerResult::NeedMoreData)));
+        assert!(matches!(instance.handle_received_data(&[b'a'; 64]), Err(TapsError::MessageTooLarge)));
+
+        let mut instance = small_http_client_instance();
+        send_request(&mut instance, "GET");
+        assert!(matches!(instance.handle_received_data(&[b'a'; 65]), Err(TapsError::MessageTooLarge)));
+
+        // Lengths which overflow are rejected rather than wrapping
+        let mut instance = http_client_instance();
+        send_request(&mut instance, "GET");
+        let response = b"HTTP/1.1 200 OK\r\nContent-Length: 18446744073709551615\r\n\r\n";
+        assert!(matches!(instance.handle_received_data(response), Err(TapsError::MessageTooLarge)));
+
+        let mut instance = http_client_instance();
+        send_request(&mut instance, "GET");
+        let response = b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\nffffffffffffffff\r\n";
+        assert!(matches!(instance.handle_received_data(response), Err(TapsError::MessageParseFailed(_))));
+    }
+
+    #[test]
+    fn http_server_max_message_size_test() {
+        let mut instance = small_http_server_instance();
+        let requests = messages(instance.handle_received_data(b"POST / HTTP/1.1\r\nContent-Length: 2\r\n\r\nhiGET / HTTP/1.1\r\n\r\n"));
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0].body(), b"hi");
+
+        // A request which would be too large is rejected before its body is received
+        assert!(matches!(instance.handle_received_data(b"POST / HTTP/1.1\r\nContent-Length: 100\r\n\r\n"), Err(TapsError::MessageTooLarge)));
+
+        // As is a chunked request, or a head, which grows too large
+        let mut instance = small_http_server_instance();
+        assert!(matches!(instance.handle_received_data(b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n"), Ok(FramerResult::NeedMoreData)));
+        assert!(matches!(instance.handle_received_data(b"30\r\n"), Ok(FramerResult::NeedMoreData)));
+        assert!(matches!(instance.handle_received_data(&[b'a'; 48]), Err(TapsError::MessageTooLarge)));
+
+        let mut instance = small_http_server_instance();
+        assert!(matches!(instance.handle_received_data(&[b'a'; 65]), Err(TapsError::MessageTooLarge)));
+
+        // Lengths which overflow are rejected rather than wrapping
+        let mut instance = small_http_server_instance();
+        let request = b"POST / HTTP/1.1\r\nContent-Length: 18446744073709551615\r\n\r\n";
+        assert!(matches!(instance.handle_received_data(request), Err(TapsError::MessageTooLarge)));
+    }
+
+    #[test]
+    fn length_prefix_round_trip_test() {
+        let sent_messages = vec![b"hello".to_vec(), vec![], vec![7; 200], vec![9; 300]];
+
+        for width in [PrefixWidth::U16, PrefixWidth::U32, PrefixWidth::U64, PrefixWidth::Varint].iter() {
+            for endianness in [Endianness::Big, Endianness::Little].iter() {
+                let mut framer = LengthPrefixFramer::new();
+                framer.with_width(*width);
+                framer.with_endianness(*endianness);
+                round_trip(&framer, &sent_messages);
+            }
+        }
+
+        // Messages must fit in a one byte prefix
+        let mut framer = LengthPrefixFramer::new();
+        framer.with_width(PrefixWidth::U8);
+        round_trip(&framer, &sent_messages[..3]);
+
+        let mut instance = Framer::<Vec<u8>, Vec<u8>>::new_instance(&framer);
+        let result = instance.new_sent_message(Message::new(sent_messages[3].clone(), None));
+        assert!(matches!(result, Err(TapsError::MessageFramingFailed(_))));
+    }
+
+    #[test]
+    fn length_prefix_encoding_test() {
+        let mut framer = LengthPrefixFramer::new();
+        framer.with_width(PrefixWidth::U16);
+        framer.with_endianness(Endianness::Little);
+        let mut instance = Framer::<Vec<u8>, Vec<u8>>::new_instance(&framer);
+        assert_eq!(instance.new_sent_message(Message::new(vec![1; 258], None)).unwrap()[..3], [2, 1, 1]);
+
+        // 300 is 0b10_0101100 in unsigned LEB128
+        framer.with_width(PrefixWidth::Varint);
+        let mut instance = Framer::<Vec<u8>, Vec<u8>>::new_instance(&framer);
+        assert_eq!(instance.new_sent_message(Message::new(vec![1; 300], None)).unwrap()[..3], [0xac, 0x02, 1]);
+    }
+
+    #[test]
+    fn length_prefix_max_message_size_test() {
+        let mut framer = LengthPrefixFramer::new();
+        framer.with_max_message_size(4);
+
+        let mut instance = Framer::<Vec<u8>, Vec<u8>>::new_instance(&framer);
+        let result = instance.new_sent_message(Message::new(b"hello".to_vec(), None));
+        assert!(matches!(result, Err(TapsError::MessageFramingFailed(_))));
+
+        // A message which is too large is rejected as soon as its prefix is received
+        let mut instance = Framer::<Vec<u8>, Vec<u8>>::new_instance(&framer);
+        assert!(matches!(instance.handle_received_data(&[0, 0, 0, 5]), Err(TapsError::MessageTooLarge)));
+
+        // As is a varint prefix longer than any 64-bit length
+        framer.with_width(PrefixWidth::Varint);
+        let mut instance = Framer::<Vec<u8>, Vec<u8>>::new_instance(&framer);
+        assert!(matches!(instance.handle_received_data(&[0x80; 11]), Err(TapsError::MessageParseFailed(_))));
+
+        // A message which was cut short is truncated
+        let mut instance = Framer::<Vec<u8>, Vec<u8>>::new_instance(&framer);
+        assert!(matches!(instance.handle_received_data(&[3, b'a']), Ok(FramerResult::NeedMoreData)));
+        assert!(matches!(instance.handle_end_of_data(), Err(TapsError::MessageParseFailed(_))));
+    }
+
+    #[test]
+    fn delimiter_round_trip_test() {
+        let sent_messages = vec![b"hello".to_vec(), vec![], b"world\r".to_vec()];
+        round_trip(&DelimiterFramer::new(b"\n").unwrap(), &sent_messages);
+
+        // A multi-byte delimiter may be split across reads, and messages may contain part of it
+        let framer = DelimiterFramer::new(b"\r\n").unwrap();
+        round_trip(&framer, &[b"a\rb\nc".to_vec(), b"\n".to_vec(), b"\r".to_vec()]);
+
+        // A final message need not be followed by the delimiter
+        let mut instance = Framer::<Vec<u8>, Vec<u8>>::new_instance(&framer);
+        assert_eq!(messages(instance.handle_received_data(b"one\r\ntwo")), vec![b"one".to_vec()]);
+        assert_eq!(instance.handle_end_of_data().unwrap(), Some(b"two".to_vec()));
+        assert!(matches!(instance.handle_end_of_data(), Ok(None)));
+    }
+
+    #[test]
+    fn delimiter_in_message_test() {
+        let framer = DelimiterFramer::new(b"\r\n").unwrap();
+
+        // A message containing the delimiter would be received as two messages
+        let mut instance = Framer::<Vec<u8>, Vec<u8>>::new_instance(&framer);
+        let result = instance.new_sent_message(Message::new(b"one\r\ntwo".to_vec(), None));
+        assert!(matches!(result, Err(TapsError::MessageFramingFailed(_))));
+    }
+
+    #[test]
+    fn empty_delimiter_test() {
+        // Every byte would be a message
+        assert!(matches!(DelimiterFramer::new(b""), Err(TapsError::InvalidDelimiter)));
+    }
+
+    #[test]
+    fn delimiter_max_line_length_test() {
+        let mut framer = DelimiterFramer::new(b"\n").unwrap();
+        framer.with_max_line_length(4);
+
+        let mut instance = Framer::<Vec<u8>, Vec<u8>>::new_instance(&framer);
+        let result = instance.new_sent_message(Message::new(b"hello".to_vec(), None));
+        assert!(matches!(result, Err(TapsError::MessageFramingFailed(_))));
+
+        // A line which is too long is rejected once it has, or could have, ended
+        let mut instance = Framer::<Vec<u8>, Vec<u8>>::new_instance(&framer);
+        assert!(matches!(instance.handle_received_data(b"hello\n"), Err(TapsError::MessageTooLarge)));
+
+        let mut instance = Framer::<Vec<u8>, Vec<u8>>::new_instance(&framer);
+        assert!(matches!(instance.handle_received_data(b"abcd"), Ok(FramerResult::NeedMoreData)));
+        assert!(matches!(instance.handle_received_data(b"ef"), Err(TapsError::MessageTooLarge)));
+
+        // What was received of the line can be delivered as a partial message
+        assert_eq!(instance.take_partial_data(), b"abcdef");
+        assert!(instance.take_partial_data().is_empty());
+    }
+
+    #[test]
+    fn framer_stack_test() {
+        let mut length_prefix_framer = LengthPrefixFramer::new();
+        length_prefix_framer.with_width(PrefixWidth::U16);
+
+        // Lines, each carried in a length-prefixed message
+        let mut stack = FramerStack::<Vec<u8>, Vec<u8>>::new(Arc::new(DelimiterFramer::new(b"\n").unwrap()));
+        stack.with_layer(Arc::new(length_prefix_framer));
+        round_trip(&stack, &[b"one".to_vec(), b"two".to_vec(), vec![]]);
+
+        let mut sender = stack.new_instance();
+        assert_eq!(sender.new_sent_message(Message::new(b"one".to_vec(), None)).unwrap(), b"\x00\x04one\n".to_vec());
+
+        // A length-prefixed message may carry several lines, the last of which ends with the data
+        let mut data = vec![];
+        for message in [b"one\ntwo".to_vec(), b"\nthree".to_vec()].iter() {
+            let mut instance = Framer::<Vec<u8>, Vec<u8>>::new_instance(&length_prefix_framer);
+            data.extend(instance.new_sent_message(Message::new(message.clone(), None)).unwrap());
+        }
+        let mut receiver = stack.new_instance();
+        assert_eq!(messages(receiver.handle_received_data(&data)), vec![b"one".to_vec(), b"two".to_vec()]);
+        assert_eq!(receiver.handle_end_of_data().unwrap(), Some(b"three".to_vec()));
+        assert!(matches!(receiver.handle_end_of_data(), Ok(None)));
+
+        // Length-prefixed messages, each carried on a line. The last line ends with the data, so the
+        // message it carries is only returned once the remote endpoint has finished sending.
+        let mut varint_framer = LengthPrefixFramer::new();
+        varint_framer.with_width(PrefixWidth::Varint);
+        let mut stack = FramerStack::<Vec<u8>, Vec<u8>>::new(Arc::new(varint_framer));
+        stack.with_layer(Arc::new(DelimiterFramer::new(b"\n").unwrap()));
+
+        let mut receiver = stack.new_instance();
+        assert_eq!(messages(receiver.handle_received_data(b"\x01a\n\x01b")), vec![b"a".to_vec()]);
+        assert_eq!(receiver.handle_end_of_data().unwrap(), Some(b"b".to_vec()));
+        assert!(matches!(receiver.handle_end_of_data(), Ok(None)));
+    }
+
+    #[test]
+    fn http_framer_stack_test() {
+        let mut client_stack = FramerStack::<Request<()>, Response<Vec<u8>>>::new(Arc::new(HttpClientFramer::new()));
+        client_stack.with_layer(Arc::new(LengthPrefixFramer::new()));
+        let mut server_stack = FramerStack::<Response<Vec<u8>>, Request<Vec<u8>>>::new(Arc::new(HttpServerFramer::new()));
+        server_stack.with_layer(Arc::new(LengthPrefixFramer::new()));
+
+        let mut client = client_stack.new_instance();
+        let mut server = server_stack.new_instance();
+
+        // Requests framed by the client stack are parsed by the server stack, and responses the other way
+        let request = Request::builder().method("GET").uri("http://example.com/path").body(()).unwrap();
+        let request_data = client.new_sent_message(Message::new(request, None)).unwrap();
+        assert_eq!(request_data[..4], (request_data.len() as u32 - 4).to_be_bytes());
+
+        let requests = messages(server.handle_received_data(&request_data));
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].uri().path(), "/path");
+
+        let response = Response::builder().status(200).body(b"body".to_vec()).unwrap();
+        let response_data = server.new_sent_message(Message::new(response, None)).unwrap();
+
+        let (start, end) = response_data.split_at(6);
+        assert!(matches!(client.handle_received_data(start), Ok(FramerResult::NeedMoreData)));
+        let responses = messages(client.handle_received_data(end));
+        assert_eq!(responses.len(), 1);
+        assert_eq!(responses[0].body(), b"body");
+
+        assert!(matches!(client.handle_end_of_data(), Ok(None)));
+        assert!(matches!(server.handle_end_of_data(), Ok(None)));
     }
 }
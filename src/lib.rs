@@ -1,10 +1,21 @@
 pub mod endpoint;
 pub mod preconnection;
+pub mod candidate;
 pub mod transport_properties;
+pub mod security_parameters;
 pub mod selection_properties;
 pub mod connection;
+pub mod connection_event;
+pub mod connection_properties;
 pub mod listener;
+pub mod listener_event;
+pub mod remote_filter;
 pub mod framer;
+#[cfg(feature = "serde-framer")]
+pub mod serde_framer;
 pub mod error;
 pub mod message;
 pub mod message_context;
+pub mod quic;
+pub mod udp;
+mod socket_options;
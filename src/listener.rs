@@ -1,73 +1,263 @@
 use crate::preconnection::Preconnection;
 use crate::connection::Connection;
+use crate::endpoint::LocalEndpoint;
 use crate::error::TapsError;
+use crate::listener_event::ListenerEvent;
 use crate::preconnection::TransportInstance;
+use crate::quic::QuicListener;
+use crate::remote_filter::{IpNetwork, RemoteFilter, RemoteRule};
+use crate::socket_options;
+use crate::udp::UdpListener;
 
+use std::future::Future;
 use std::pin::Pin;
+use std::io;
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::ops::RangeInclusive;
+use std::sync::{Arc, Mutex, RwLock};
 
 use async_std::{
     stream::Stream,
     task,
-    task::{Context, Poll},
+    task::{Context, JoinHandle, Poll, Waker},
     net::{SocketAddr, ToSocketAddrs, TcpListener}
 };
 
-use futures::stream::{FuturesUnordered, StreamExt};
+use futures::channel::mpsc::{unbounded, UnboundedReceiver, UnboundedSender};
+use futures::future::{abortable, poll_fn, AbortHandle};
+use futures::stream::StreamExt;
+use socket2::{Protocol, Type};
 
-pub struct Listener<'a, T, U> {
-    preconnection: Preconnection<'a, T, U>,
-    allowed_remote_addrs: Vec<SocketAddr>,
-    tcp_listener: Option<TcpListener>,
+// Each protocol listener is run by its own accept task, which passes new transport instances
+// to the Listener over a channel. Polling the Listener therefore never blocks the executor.
+pub struct Listener<T, U> {
+    preconnection: Preconnection<T, U>,
+    // Listened on together with the local endpoint of the Preconnection
+    local_endpoints: Vec<LocalEndpoint>,
+    remote_filter: RemoteFilter,
+    event_sender: Arc<Mutex<Option<UnboundedSender<ListenerEvent>>>>,
+    connection_limit: Arc<Mutex<ConnectionLimit>>,
+    // Accept tasks and host name refresh task, cancelled when the Listener is stopped or dropped
+    tasks: Vec<(JoinHandle<()>, AbortHandle)>,
+    incoming_sender: UnboundedSender<TransportInstance>,
+    incoming: UnboundedReceiver<TransportInstance>,
 }
 
-impl<'a, T, U> Listener<'a, T, U> {
+// A protocol listener bound to one local address, before its accept task is started
+enum ProtocolListener {
+    Tcp(TcpListener),
+    Udp(UdpListener),
+    Quic(QuicListener),
+}
+
+// The number of new Connections the Listener may still deliver. While it is zero the accept tasks
+// stop accepting, so new Connections are left queued until the limit is raised, by the transport
+// for TCP and in the backlog of the protocol listener for UDP and QUIC.
+struct ConnectionLimit {
+    remaining: usize, // usize::MAX, the default, means no limit
+    // Accept tasks waiting for the limit to be raised
+    wakers: Vec<Waker>,
+}
+
+// State shared by the accept tasks of a Listener
+struct AcceptState {
+    remote_filter: RemoteFilter,
+    // Rules for the allowed host names, replaced each time they are resolved
+    resolved_rules: RwLock<Vec<RemoteRule>>,
+    event_sender: Arc<Mutex<Option<UnboundedSender<ListenerEvent>>>>,
+    connection_limit: Arc<Mutex<ConnectionLimit>>,
+    incoming_sender: UnboundedSender<TransportInstance>,
+}
+
+impl<T, U> Listener<T, U> {
     pub fn new(
-        preconnection: Preconnection<'a, T, U>,
-    ) -> Listener<'a, T, U> {
+        preconnection: Preconnection<T, U>,
+    ) -> Listener<T, U> {
+        let (incoming_sender, incoming) = unbounded();
+
         Listener::<T, U> {
             preconnection: preconnection,
-            allowed_remote_addrs: vec![],
-            tcp_listener: None,
+            local_endpoints: vec![],
+            remote_filter: RemoteFilter::new(),
+            event_sender: Arc::new(Mutex::new(None)),
+            connection_limit: Arc::new(Mutex::new(ConnectionLimit {
+                remaining: usize::MAX,
+                wakers: vec![],
+            })),
+            tasks: vec![],
+            incoming_sender: incoming_sender,
+            incoming: incoming,
+        }
+    }
+
+    // Also listen on another local endpoint. Connections from every local endpoint are returned
+    // by the same Listener. Must be added before the Listener is started.
+    pub fn with_local_endpoint(&mut self, local_endpoint: LocalEndpoint) -> () {
+        self.local_endpoints.push(local_endpoint);
+    }
+
+    // Further restricts the remote endpoints accepted, in addition to the remote endpoint of the
+    // Preconnection. Must be set before the Listener is started.
+    pub fn with_remote_filter(&mut self, remote_filter: RemoteFilter) -> () {
+        self.remote_filter = remote_filter;
+    }
+
+    pub fn events(&mut self) -> UnboundedReceiver<ListenerEvent> {
+        let (event_sender, events) = unbounded();
+        *self.event_sender.lock().unwrap() = Some(event_sender);
+        return events;
+    }
+
+    // Limit the number of new Connections delivered, as in TAPS. Each delivered Connection reduces
+    // the limit by one, and once it reaches zero new Connections are held back until it is raised.
+    pub fn set_new_connection_limit(&mut self, limit: usize) -> () {
+        let mut connection_limit = self.connection_limit.lock().unwrap();
+        connection_limit.remaining = limit;
+
+        for waker in connection_limit.wakers.drain(..) {
+            waker.wake();
+        }
+    }
+
+    // Close every protocol listener and end the stream of new Connections. Connections which have
+    // already been delivered are not affected.
+    pub async fn stop(&mut self) -> () {
+        // Wait for the tasks to finish, so that their sockets have been closed when this returns
+        for (task, abort_handle) in self.tasks.drain(..) {
+            abort_handle.abort();
+            task.await;
+        }
+
+        // Close any Connections accepted but not yet delivered, and end the stream
+        self.incoming.close();
+        while let Ok(_) = self.incoming.try_recv() {}
+
+        if let Some(event_sender) = self.event_sender.lock().unwrap().as_ref() {
+            event_sender.unbounded_send(ListenerEvent::Stopped).ok();
         }
     }
 
     pub async fn start(&mut self) -> Result<(), TapsError> {
-        let local_port = self.preconnection.local_endpoint.as_ref().unwrap().port.as_ref().unwrap();
-        let local_addr = self.preconnection.local_endpoint.as_ref().unwrap().address.as_ref().unwrap();
-        let local_socket_addr = format!("{}:{}", local_addr, local_port).to_socket_addrs().await?.next().unwrap();
         let candidate_protocol_ranks = self.preconnection.calculate_candidate_protocol_ranks()?;
-        let candidate_protocols = candidate_protocol_ranks.keys();
-
-        // Gather allowed remote endpoints - if provided
-        if self.preconnection.remote_endpoint.is_some() {
-            let remote_port = self.preconnection.remote_endpoint.as_ref().unwrap().port.as_ref().unwrap();
-
-            // IP address provided in remote endpoint
-            if self.preconnection.remote_endpoint.as_ref().unwrap().address.is_some() {
-                let remote_addr = self.preconnection.remote_endpoint.as_ref().unwrap().address.as_ref().unwrap();
-                let from_addr = format!("{}:{}", remote_addr, remote_port).to_socket_addrs().await?;
-                for a in from_addr {
-                    self.allowed_remote_addrs.push(a);
-                }
+        let candidate_protocols: Vec<&str> = candidate_protocol_ranks.keys().cloned().collect();
+
+        let local_endpoints = self.preconnection.local_endpoint.iter().chain(self.local_endpoints.iter());
+        if local_endpoints.clone().next().is_none() {
+            return Err(TapsError::LocalEndpointNotProvided);
+        }
+
+        // Bind every protocol listener before starting any, so that nothing is left running if
+        // one of the local addresses cannot be bound
+        let mut protocol_listeners = vec![];
+        for local_endpoint in local_endpoints {
+            let local_port = match local_endpoint.port {
+                Some(local_port) => local_port,
+                None => return Err(TapsError::LocalEndpointPortNotProvided),
+            };
+
+            match local_endpoint.address.as_ref() {
+                Some(local_addr) => {
+                    let mut local_socket_addrs: Vec<SocketAddr> = (local_addr.as_str(), local_port).to_socket_addrs().await?.collect();
+                    local_socket_addrs.sort();
+                    local_socket_addrs.dedup();
+
+                    for local_socket_addr in local_socket_addrs {
+                        self.bind(local_socket_addr, &candidate_protocols, &mut protocol_listeners).await?;
+                    }
+                },
+                None => {
+                    // Listen on every address of both IP families, tolerating hosts without IPv6
+                    let ipv4_wildcard_addr = SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), local_port);
+                    let ipv6_wildcard_addr = SocketAddr::new(Ipv6Addr::UNSPECIFIED.into(), local_port);
+                    self.bind(ipv4_wildcard_addr, &candidate_protocols, &mut protocol_listeners).await?;
+                    match self.bind(ipv6_wildcard_addr, &candidate_protocols, &mut protocol_listeners).await {
+                        Ok(()) => (),
+                        Err(TapsError::Io(ref e)) if socket_options::ipv6_unavailable(e) => (),
+                        Err(e) => return Err(e),
+                    }
+                },
+            }
+        }
+
+        // The remote endpoint, if provided, restricts the remote endpoints accepted. The address
+        // may be a range in CIDR notation, and the port applies only if given.
+        let mut remote_filter = self.remote_filter.clone();
+        if let Some(remote_endpoint) = self.preconnection.remote_endpoint.as_ref() {
+            let ports = remote_endpoint.port.map(|port| port..=port);
+
+            if let Some(address) = remote_endpoint.address.as_ref() {
+                remote_filter.with_allowed(address.parse::<IpNetwork>()?, ports.clone());
+            }
+
+            if let Some(host_name) = remote_endpoint.host_name.as_ref() {
+                remote_filter.with_allowed_host_name(host_name, ports);
             }
+        }
+
+        let resolved_rules = resolve_host_names(&remote_filter.allowed_host_names).await;
+        let accept_state = Arc::new(AcceptState {
+            remote_filter: remote_filter,
+            resolved_rules: RwLock::new(resolved_rules),
+            event_sender: self.event_sender.clone(),
+            connection_limit: self.connection_limit.clone(),
+            incoming_sender: self.incoming_sender.clone(),
+        });
+
+        // Each accept task delivers to the same channel, merging the Connections into one stream
+        for protocol_listener in protocol_listeners {
+            let accept_state = accept_state.clone();
 
-            // Host name provided in remote endpoint - DNS lookup performed here
-            if self.preconnection.remote_endpoint.as_ref().unwrap().host_name.is_some() {
-                let host_name = self.preconnection.remote_endpoint.as_ref().unwrap().host_name.as_ref().unwrap();
-                let from_host_name = format!("{}:{}", host_name, remote_port).to_socket_addrs().await?;
-                for a in from_host_name {
-                    self.allowed_remote_addrs.push(a);
-                }
+            match protocol_listener {
+                ProtocolListener::Tcp(tcp_listener) => self.spawn(accept_tcp(tcp_listener, accept_state)),
+                ProtocolListener::Udp(udp_listener) => self.spawn(accept_udp(udp_listener, accept_state)),
+                ProtocolListener::Quic(quic_listener) => self.spawn(accept_quic(quic_listener, accept_state)),
             }
         }
 
+        if !accept_state.remote_filter.allowed_host_names.is_empty() {
+            self.spawn(refresh_host_names(accept_state));
+        }
+
+        return Ok(());
+    }
+
+    // Run a task of the Listener. The task can be aborted without awaiting it, as when dropped.
+    fn spawn<F: Future<Output = ()> + Send + 'static>(&mut self, future: F) -> () {
+        let (future, abort_handle) = abortable(future);
+        let task = task::spawn(async move {
+            future.await.ok();
+        });
+        self.tasks.push((task, abort_handle));
+    }
+
+    // Bind a listener for each candidate protocol to the local address
+    async fn bind(
+        &self,
+        local_socket_addr: SocketAddr,
+        candidate_protocols: &[&str],
+        protocol_listeners: &mut Vec<ProtocolListener>,
+    ) -> Result<(), TapsError> {
         for protocol in candidate_protocols {
             match *protocol {
                 "tcp" => {
-                    self.tcp_listener = Some(TcpListener::bind(local_socket_addr).await?);
+                    let tcp_listener = bind_tcp(local_socket_addr)?;
+                    protocol_listeners.push(ProtocolListener::Tcp(tcp_listener));
+                },
+                "udp" => {
+                    let udp_listener = UdpListener::bind(local_socket_addr).await?;
+                    protocol_listeners.push(ProtocolListener::Udp(udp_listener));
+                },
+                "quic" => {
+                    // A QUIC server cannot be run without a certificate and private key
+                    let security_parameters = self.preconnection.security_parameters.as_ref();
+                    let certificate_chain = security_parameters.and_then(|s| s.certificate_chain.as_deref());
+                    let private_key = security_parameters.and_then(|s| s.private_key.as_deref());
+
+                    if let (Some(certificate_chain), Some(private_key)) = (certificate_chain, private_key) {
+                        let quic_listener = QuicListener::bind(local_socket_addr, certificate_chain, private_key).await?;
+                        protocol_listeners.push(ProtocolListener::Quic(quic_listener));
+                    }
                 },
-                "udp" => {/* TODO */},
-                "quic" => {/* TODO */},
                 _ => {},
             }
         }
@@ -75,52 +265,185 @@ impl<'a, T, U> Listener<'a, T, U> {
         return Ok(());
     }
 
-    async fn poll_tcp(&self) -> Connection<'a, T, U> {
-        loop {
-            let incoming_conn = self.tcp_listener.as_ref().unwrap().accept().await.unwrap();
-            if self.allowed_remote_addrs.contains(&incoming_conn.1) {
-                return Connection::new(
-                    Preconnection::new(self.preconnection.local_endpoint, self.preconnection.remote_endpoint, self.preconnection.transport_properties, self.preconnection.framer),
-                    TransportInstance {
-                        tcp_stream_instance: Some(incoming_conn.0),
-                        udp_socket_instance: None,
-                        quic_stream_instance: None,
-                    }
-                );
+}
+
+fn bind_tcp(local_socket_addr: SocketAddr) -> io::Result<TcpListener> {
+    let socket = socket_options::listener_socket(local_socket_addr, Type::STREAM, Protocol::TCP)?;
+    socket.set_reuse_address(true)?;
+    socket.bind(&local_socket_addr.into())?;
+    socket.listen(128)?;
+
+    return Ok(TcpListener::from(std::net::TcpListener::from(socket)));
+}
+
+impl AcceptState {
+    // Whether a Connection from the remote address is accepted. Rejected remote endpoints are
+    // reported to the application rather than dropped silently.
+    fn accepts(&self, remote_addr: SocketAddr) -> bool {
+        let resolved_rules = self.resolved_rules.read().unwrap();
+        if self.remote_filter.accepts(remote_addr, &resolved_rules) {
+            return true;
+        }
+
+        if let Some(event_sender) = self.event_sender.lock().unwrap().as_ref() {
+            event_sender.unbounded_send(ListenerEvent::ConnectionRejected(remote_addr)).ok();
+        }
+        return false;
+    }
+
+    // Wait until the connection limit allows another Connection. If take is set, the Connection
+    // is also counted against the limit.
+    async fn wait_for_connection_limit(&self, take: bool) -> () {
+        return poll_fn(|cx| {
+            let mut connection_limit = self.connection_limit.lock().unwrap();
+
+            if connection_limit.remaining == 0 {
+                connection_limit.wakers.push(cx.waker().clone());
+                return Poll::Pending;
+            }
+
+            if take && connection_limit.remaining != usize::MAX {
+                connection_limit.remaining -= 1;
+            }
+            return Poll::Ready(());
+        }).await;
+    }
+
+    // Deliver a new Connection to the Listener once the connection limit allows it
+    async fn deliver(&self, transport_instance: TransportInstance) -> bool {
+        self.wait_for_connection_limit(true).await;
+        return self.incoming_sender.unbounded_send(transport_instance).is_ok();
+    }
+}
+
+// Resolve each allowed host name to rules for its addresses. Host names which fail to resolve are
+// left out, and are tried again at the next refresh.
+async fn resolve_host_names(host_names: &[(String, Option<RangeInclusive<u16>>)]) -> Vec<RemoteRule> {
+    let mut rules = vec![];
+
+    for (host_name, ports) in host_names {
+        if let Ok(addrs) = (host_name.as_str(), 0).to_socket_addrs().await {
+            for addr in addrs {
+                rules.push(RemoteRule::new(IpNetwork::host(addr.ip()), ports.clone()));
             }
         }
     }
 
+    return rules;
 }
 
-impl<'a, T, U> Stream for Listener<'a, T, U> {
-    type Item = Connection<'a, T, U>;
+// Re-resolve the allowed host names every resolve interval, until the Listener is dropped
+async fn refresh_host_names(accept_state: Arc<AcceptState>) {
+    while !accept_state.incoming_sender.is_closed() {
+        task::sleep(accept_state.remote_filter.resolve_interval).await;
+
+        let resolved_rules = resolve_host_names(&accept_state.remote_filter.allowed_host_names).await;
+        *accept_state.resolved_rules.write().unwrap() = resolved_rules;
+    }
+}
 
-    fn poll_next(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
-        // Return any new transport instances as Connection objects
+async fn accept_tcp(
+    tcp_listener: TcpListener,
+    accept_state: Arc<AcceptState>,
+) {
+    while !accept_state.incoming_sender.is_closed() {
+        accept_state.wait_for_connection_limit(false).await;
 
-        let mut futures = FuturesUnordered::new();
+        let (stream, remote_addr) = match tcp_listener.accept().await {
+            Ok(incoming_conn) => incoming_conn,
+            Err(_) => continue,
+        };
 
-        if self.tcp_listener.is_some() {
-            futures.push(self.poll_tcp());
+        if accept_state.accepts(remote_addr) {
+            accept_state.deliver(TransportInstance {
+                tcp_stream_instance: Some(stream),
+                udp_socket_instance: None,
+                quic_stream_instance: None,
+            }).await;
         }
+    }
+}
+
+async fn accept_udp(
+    mut udp_listener: UdpListener,
+    accept_state: Arc<AcceptState>,
+) {
+    loop {
+        accept_state.wait_for_connection_limit(false).await;
+
+        let (connection, remote_addr) = match udp_listener.accept().await {
+            Some(incoming_conn) => incoming_conn,
+            None => break,
+        };
 
-        // Check for other listeners when implemented and add future to futures list
+        if accept_state.accepts(remote_addr) {
+            let transport_instance = TransportInstance {
+                tcp_stream_instance: None,
+                udp_socket_instance: Some(connection),
+                quic_stream_instance: None,
+            };
 
-        if futures.is_empty() {
-            return Poll::Pending;
+            if !accept_state.deliver(transport_instance).await {
+                break;
+            }
         }
+    }
+}
 
-        let mut conn = None;
+async fn accept_quic(
+    mut quic_listener: QuicListener,
+    accept_state: Arc<AcceptState>,
+) {
+    loop {
+        accept_state.wait_for_connection_limit(false).await;
 
-        task::block_on(async {
-            conn = futures.next().await;
-        });
+        let (quic_stream, remote_addr) = match quic_listener.accept().await {
+            Some(incoming_conn) => incoming_conn,
+            None => break,
+        };
+
+        if accept_state.accepts(remote_addr) {
+            // Each accepted Connection uses a bidirectional stream opened by the client
+            let transport_instance = TransportInstance {
+                tcp_stream_instance: None,
+                udp_socket_instance: None,
+                quic_stream_instance: Some(quic_stream),
+            };
+
+            if !accept_state.deliver(transport_instance).await {
+                break;
+            }
+        } else {
+            // The listener's task still holds the connection, so it must be closed explicitly
+            // rather than left open until it times out
+            quic_stream.connection.refuse();
+        }
+    }
+}
 
-        if conn.is_some() {
-            return Poll::Ready(Some(conn.unwrap()));
+// A Listener which is dropped without being stopped must still close its protocol listeners
+impl<T, U> Drop for Listener<T, U> {
+    fn drop(&mut self) {
+        for (_, abort_handle) in self.tasks.iter() {
+            abort_handle.abort();
         }
+    }
+}
 
-        return Poll::Pending;  
+impl<T, U> Stream for Listener<T, U> {
+    type Item = Connection<T, U>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        // Return any new transport instances as Connection objects. The channel stores the
+        // task's waker, so it is woken when an accept task delivers the next transport instance.
+        let listener = self.get_mut();
+
+        match listener.incoming.poll_next_unpin(cx) {
+            Poll::Ready(Some(transport_instance)) => {
+                return Poll::Ready(Some(Connection::new(listener.preconnection.clone(), transport_instance)));
+            },
+            Poll::Ready(None) => return Poll::Ready(None),
+            Poll::Pending => return Poll::Pending,
+        }
     }
 }
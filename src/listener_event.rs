@@ -0,0 +1,9 @@
+use std::net::SocketAddr;
+
+// Events delivered on the stream returned by Listener::events(). New Connections themselves are
+// returned by polling the Listener.
+#[derive(Debug)]
+pub enum ListenerEvent {
+    ConnectionRejected(SocketAddr), // Remote endpoint not accepted by the Listener's remote filter
+    Stopped,
+}
@@ -13,4 +13,8 @@ impl<T> Message<T> {
             message_context: message_context,
         }
     }
+
+    pub fn message_context(&self) -> Option<MessageContext> {
+        return self.message_context;
+    }
 }
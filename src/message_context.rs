@@ -1,10 +1,96 @@
+use std::time::Duration;
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum CapacityProfile {
+    Default,
+    Scavenger,
+    LowLatencyInteractive,
+    LowLatencyNonInteractive,
+    ConstantRateStreaming,
+    CapacitySeeking,
+}
+
+impl CapacityProfile {
+    // DSCP codepoint used to mark packets sent with this capacity profile
+    pub fn dscp(&self) -> u8 {
+        match self {
+            CapacityProfile::Default                    => 0,  // DF
+            CapacityProfile::Scavenger                  => 1,  // LE
+            CapacityProfile::LowLatencyInteractive      => 46, // EF
+            CapacityProfile::LowLatencyNonInteractive   => 18, // AF21
+            CapacityProfile::ConstantRateStreaming      => 26, // AF31
+            CapacityProfile::CapacitySeeking            => 10, // AF11
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
-pub struct MessageContext;
+pub struct MessageContext {
+    pub lifetime: Option<Duration>, // None for an infinite lifetime
+    pub priority: u32, // Lower values have higher priority
+    pub ordered: Option<bool>, // None to use the Connection's ordering
+    pub safely_replayable: bool,
+    pub final_message: bool,
+    pub checksum_len: Option<usize>, // None for full checksum coverage
+    pub reliable: Option<bool>, // None to use the Connection's reliability
+    pub capacity_profile: CapacityProfile,
+    pub no_fragmentation: bool,
+    pub no_segmentation: bool,
+}
 
 impl MessageContext {
     pub fn new() -> MessageContext {
         MessageContext {
-            
+            lifetime: None,
+            priority: 100,
+            ordered: None,
+            safely_replayable: false,
+            final_message: false,
+            checksum_len: None,
+            reliable: None,
+            capacity_profile: CapacityProfile::Default,
+            no_fragmentation: false,
+            no_segmentation: false,
         }
     }
+
+    pub fn with_lifetime(&mut self, lifetime: Duration) -> () {
+        self.lifetime = Some(lifetime);
+    }
+
+    pub fn with_priority(&mut self, priority: u32) -> () {
+        self.priority = priority;
+    }
+
+    pub fn with_ordered(&mut self, ordered: bool) -> () {
+        self.ordered = Some(ordered);
+    }
+
+    pub fn with_safely_replayable(&mut self, safely_replayable: bool) -> () {
+        self.safely_replayable = safely_replayable;
+    }
+
+    pub fn with_final(&mut self, final_message: bool) -> () {
+        self.final_message = final_message;
+    }
+
+    pub fn with_checksum_len(&mut self, checksum_len: usize) -> () {
+        self.checksum_len = Some(checksum_len);
+    }
+
+    pub fn with_reliable(&mut self, reliable: bool) -> () {
+        self.reliable = Some(reliable);
+    }
+
+    pub fn with_capacity_profile(&mut self, capacity_profile: CapacityProfile) -> () {
+        self.capacity_profile = capacity_profile;
+    }
+
+    pub fn with_no_fragmentation(&mut self, no_fragmentation: bool) -> () {
+        self.no_fragmentation = no_fragmentation;
+    }
+
+    pub fn with_no_segmentation(&mut self, no_segmentation: bool) -> () {
+        self.no_segmentation = no_segmentation;
+    }
 }
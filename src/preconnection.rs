@@ -1,65 +1,102 @@
 use crate::error::TapsError;
+use crate::candidate::{AddressFamily, Candidate, CandidateSource, EndpointCandidate, ResolvedCandidates};
 use crate::endpoint::LocalEndpoint;
 use crate::endpoint::RemoteEndpoint;
 use crate::transport_properties::TransportProperties;
+use crate::security_parameters::SecurityParameters;
 use crate::connection::Connection;
 use crate::listener::Listener;
 use crate::selection_properties;
 use crate::selection_properties::ServiceLevel;
 use crate::selection_properties::PreferenceLevel;
-use crate::framer::Framer;
+use crate::framer::{Framer, RawFramer};
+use crate::quic;
+use crate::quic::{QuicConnection, QuicListener, QuicStream};
+use crate::udp;
+use crate::udp::UdpConnection;
+use crate::socket_options;
 
 use std::collections::HashMap;
-use std::collections::HashSet;
+use std::io;
 use std::time::Duration;
-use std::net::SocketAddr;
-use std::pin::Pin;
-use std::boxed::Box;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::Arc;
 
 use async_std::{
+    future,
     prelude::*,
     println,
     task,
-    net::{ToSocketAddrs, TcpStream, UdpSocket},
+    net::{ToSocketAddrs, TcpListener, TcpStream, UdpSocket},
 };
 
+use async_io::Async;
+use futures::future::{select, Either};
 use futures::stream::FuturesUnordered;
 use itertools::interleave;
 use quiche;
 use ring::rand::*;
-
-const QUIC_MAX_DATAGRAM_SIZE: usize = 1350;
+use socket2::{Domain, Protocol, Socket, Type};
 
 pub struct TransportInstance {
     pub tcp_stream_instance: Option<TcpStream>,
-    pub udp_socket_instance: Option<UdpSocket>,
-    pub quic_stream_instance: Option<Pin<Box<quiche::Connection>>>,
+    pub udp_socket_instance: Option<UdpConnection>,
+    pub quic_stream_instance: Option<QuicStream>,
 }
 
-#[derive(Clone, Copy)]
-pub struct Preconnection<'a, T, U> {
-    pub local_endpoint: Option<LocalEndpoint<'a>>,
-    pub remote_endpoint: Option<RemoteEndpoint<'a>>,
+pub struct Preconnection<T, U> {
+    pub local_endpoint: Option<LocalEndpoint>,
+    pub remote_endpoint: Option<RemoteEndpoint>,
     pub transport_properties: Option<TransportProperties>,
-    pub framer: &'a dyn Framer<T, U>,
+    pub security_parameters: Option<SecurityParameters>,
+    pub framer: Arc<dyn Framer<T, U> + Send + Sync>,
+}
+
+// Implemented manually as a derive would require the message types of the framer to be Clone
+impl<T, U> Clone for Preconnection<T, U> {
+    fn clone(&self) -> Preconnection<T, U> {
+        Preconnection {
+            local_endpoint: self.local_endpoint.clone(),
+            remote_endpoint: self.remote_endpoint.clone(),
+            transport_properties: self.transport_properties,
+            security_parameters: self.security_parameters.clone(),
+            framer: self.framer.clone(),
+        }
+    }
 }
 
-impl<'a, T, U> Preconnection<'a, T, U> {
+impl Preconnection<Vec<u8>, Vec<u8>> {
+    // Preconnection for sending and receiving raw bytes, without framing messages
+    pub fn new_raw(
+        local_endpoint: Option<LocalEndpoint>, 
+        remote_endpoint: Option<RemoteEndpoint>, 
+        transport_properties: Option<TransportProperties>,
+    ) -> Preconnection<Vec<u8>, Vec<u8>> {
+        return Preconnection::new(local_endpoint, remote_endpoint, transport_properties, Arc::new(RawFramer));
+    }
+}
+
+impl<T, U> Preconnection<T, U> {
     pub fn new(
-        local_endpoint: Option<LocalEndpoint<'a>>, 
-        remote_endpoint: Option<RemoteEndpoint<'a>>, 
+        local_endpoint: Option<LocalEndpoint>, 
+        remote_endpoint: Option<RemoteEndpoint>, 
         transport_properties: Option<TransportProperties>,
-        framer: &'a dyn Framer<T, U>,
-    ) -> Preconnection<'a, T, U> {
+        framer: Arc<dyn Framer<T, U> + Send + Sync>,
+    ) -> Preconnection<T, U> {
         Preconnection {
             local_endpoint: local_endpoint,
             remote_endpoint: remote_endpoint,
             transport_properties: transport_properties,
+            security_parameters: None,
             framer: framer,
         }
     }
 
-    pub async fn initiate(mut self) -> Result<Connection<'a, T, U>, TapsError> {
+    pub fn with_security_parameters(&mut self, security_parameters: SecurityParameters) -> () {
+        self.security_parameters = Some(security_parameters);
+    }
+
+    pub async fn initiate(self) -> Result<Connection<T, U>, TapsError> {
         // Ensure sufficient remote endpoint parameters have been supplied for Connection establishment
         if self.remote_endpoint.is_none() {
             return Err(TapsError::RemoteEndpointNotProvided);
@@ -71,140 +108,217 @@ impl<'a, T, U> Preconnection<'a, T, U> {
             return Err(TapsError::RemoteEndpointAddressAndHostNameBothNotProvided);
         }
 
+        let candidates = self.resolve().await?.candidates;
+        return self.initiate_with_candidates(candidates).await;
+    }
+
+    // Initiate a Connection by racing the given candidates, which may be a subset of those returned
+    // by resolve(), in order
+    pub async fn initiate_with_candidates(mut self, candidates: Vec<Candidate>) -> Result<Connection<T, U>, TapsError> {
         // If no Transport Properties provided, use default Transport Properties
         if self.transport_properties.is_none() {
             self.transport_properties = Some(TransportProperties::default());
         }
 
-        {
-            // Gather candidate connections
-            let candidates = self.gather_candidates().await;
-            let candidates = match candidates {
-                Ok(c) => c,
-                Err(e) => return Err(e),
-            };
-
-            // Race gatherered candidates
-            // Use delayed racing with connection attempts launched in parallel with a delay between each.
-            let mut futures = FuturesUnordered::new();
-            let mut delay = Duration::from_millis(0);
+        // Race gatherered candidates
+        // Use delayed racing with connection attempts launched in parallel with a delay between each.
+        // Attempts are driven by timers on the caller's executor, and any attempts still in progress
+        // are cancelled by dropping them once one attempt has succeeded.
+        let trusted_certificates = self.security_parameters.as_ref().and_then(|s| s.trusted_certificates.clone());
 
-            for candidate in candidates {
-                futures.push(attempt_connection(candidate, delay));
-                delay = delay + Duration::from_millis(250);
-            }
+        let mut futures = FuturesUnordered::new();
+        let mut delay = Duration::from_millis(0);
 
-            task::block_on(async {
-                while let Some(transport_instance) = futures.next().await {
-                    match transport_instance {
-                        Ok(transport_instance) => {
-                            if transport_instance.tcp_stream_instance.is_some() {
-                                println!("Connected using TCP").await;
-                            } else if transport_instance.udp_socket_instance.is_some() {
-                                println!("Connected using UDP").await;
-                            } else if transport_instance.quic_stream_instance.is_some() {
-                                println!("Connected using QUIC").await;
-                            }
-                            return Ok(Connection::new(self, transport_instance));
-                        },
-                        Err(_) => println!("Connection attempt failed").await,
-                    };
-                }
+        for candidate in candidates {
+            futures.push(attempt_connection(candidate, trusted_certificates.clone(), delay));
+            delay = delay + Duration::from_millis(250);
+        }
 
-                return Err::<Connection<'a, T, U>, TapsError>(TapsError::NoCandidateSucceeded);
-            })
+        while let Some(transport_instance) = futures.next().await {
+            match transport_instance {
+                Ok(transport_instance) => {
+                    if transport_instance.tcp_stream_instance.is_some() {
+                        println!("Connected using TCP").await;
+                    } else if transport_instance.udp_socket_instance.is_some() {
+                        println!("Connected using UDP").await;
+                    } else if transport_instance.quic_stream_instance.is_some() {
+                        println!("Connected using QUIC").await;
+                    }
+                    return Ok(Connection::new(self, transport_instance));
+                },
+                Err(_) => println!("Connection attempt failed").await,
+            };
         }
-    }
 
-    pub async fn listen(self) -> Result<Listener<'a, T, U>, TapsError> {
+        return Err(TapsError::NoCandidateSucceeded);
+    }
 
+    // Establish a Connection with a peer performing a rendezvous from its local endpoint, which is our
+    // remote endpoint, at the same time. Both peers listen and initiate at once, so a Connection can
+    // be made through NATs and firewalls which only allow traffic that was initiated from inside.
+    pub async fn rendezvous(mut self) -> Result<Connection<T, U>, TapsError> {
+        // Ensure sufficient endpoint parameters have been supplied - the peer must know our local port
+        if self.remote_endpoint.is_none() {
+            return Err(TapsError::RemoteEndpointNotProvided);
+        }
+        if self.remote_endpoint.as_ref().unwrap().port.is_none() {
+            return Err(TapsError::RemoteEndpointPortNotProvided);
+        }
+        if self.remote_endpoint.as_ref().unwrap().address.is_none() && self.remote_endpoint.as_ref().unwrap().host_name.is_none() {
+            return Err(TapsError::RemoteEndpointAddressAndHostNameBothNotProvided);
+        }
         if self.local_endpoint.is_none() {
             return Err(TapsError::LocalEndpointNotProvided);
         }
+        if self.local_endpoint.as_ref().unwrap().port.is_none() {
+            return Err(TapsError::LocalEndpointPortNotProvided);
+        }
 
+        if self.transport_properties.is_none() {
+            self.transport_properties = Some(TransportProperties::default());
+        }
+
+        // The QUIC server role requires a certificate and private key, as for a Listener
+        let security_parameters = self.security_parameters.as_ref();
+        let certificate_chain = security_parameters.and_then(|s| s.certificate_chain.clone());
+        let private_key = security_parameters.and_then(|s| s.private_key.clone());
+        let server_credentials = match (certificate_chain, private_key) {
+            (Some(certificate_chain), Some(private_key)) => Some((certificate_chain, private_key)),
+            _ => None,
+        };
+        let trusted_certificates = security_parameters.and_then(|s| s.trusted_certificates.clone());
+
+        let candidates = self.resolve().await?.candidates;
+
+        // Both peers must use the same protocol stack, so rather than racing protocol stacks only the
+        // highest ranked one which supports rendezvous is used
+        let protocol = candidates.iter().map(|c| c.protocol).find(|protocol| match *protocol {
+            "tcp" | "udp" => true,
+            "quic" => server_credentials.is_some(),
+            _ => false,
+        });
+        let protocol = match protocol {
+            Some(protocol) => protocol,
+            None => return Err(TapsError::NoCompatibleProtocolStacks),
+        };
+
+        let mut futures = FuturesUnordered::new();
+        let mut delay = Duration::from_millis(0);
+
+        for candidate in candidates.into_iter().filter(|c| c.protocol == protocol) {
+            futures.push(attempt_rendezvous(candidate, server_credentials.clone(), trusted_certificates.clone(), delay));
+            delay = delay + Duration::from_millis(250);
+        }
+
+        while let Some(transport_instance) = futures.next().await {
+            match transport_instance {
+                Ok(transport_instance) => return Ok(Connection::new(self, transport_instance)),
+                // Other candidates may still succeed
+                Err(_) => continue,
+            };
+        }
+
+        return Err(TapsError::NoCandidateSucceeded);
+    }
+
+    // The Listener listens on the local endpoint of the Preconnection, if any, and on any added to it with
+    // Listener::with_local_endpoint, so the local endpoints are only checked by Listener::start. A local
+    // endpoint without an address listens on the wildcard addresses, but every local endpoint needs a port,
+    // as remote endpoints could not otherwise know where to connect.
+    pub async fn listen(self) -> Result<Listener<T, U>, TapsError> {
         return Ok(Listener::new(self))
     }
 
-    // Perform candidate gathering for Connection initiation
-    async fn gather_candidates(&self) -> Result<std::vec::Vec<(SocketAddr, Option<SocketAddr>, &'static str)>, TapsError> {
+    // Resolve the endpoints and Transport Properties into candidates for Connection initiation, as in
+    // the TAPS Resolve action. Candidates are returned in the order initiate() races them, so that
+    // they can be inspected, or filtered and passed to initiate_with_candidates().
+    pub async fn resolve(&self) -> Result<ResolvedCandidates, TapsError> {
 
-        // Gather remote endpoint candidates
-        let remote_port = self.remote_endpoint.as_ref().unwrap().port.as_ref().unwrap();
-        let mut candidate_remote_addrs = HashSet::new();
+        // Gather remote endpoint candidates from the IP address and host name - DNS lookup performed here
+        let mut remote_candidates: Vec<EndpointCandidate> = vec![];
 
-        // IP address provided in remote endpoint
-        if self.remote_endpoint.as_ref().unwrap().address.is_some() {
-            let remote_addr = self.remote_endpoint.as_ref().unwrap().address.as_ref().unwrap();
-            let from_addr = format!("{}:{}", remote_addr, remote_port).to_socket_addrs().await?;
-            for a in from_addr {
-                candidate_remote_addrs.insert(a);
-            }
-        }
+        if let Some(remote_endpoint) = self.remote_endpoint.as_ref() {
+            let remote_port = match remote_endpoint.port {
+                Some(remote_port) => remote_port,
+                None => return Err(TapsError::RemoteEndpointPortNotProvided),
+            };
 
-        // Host name provided in remote endpoint - DNS lookup performed here
-        if self.remote_endpoint.as_ref().unwrap().host_name.is_some() {
-            let host_name = self.remote_endpoint.as_ref().unwrap().host_name.as_ref().unwrap();
-            let from_host_name = format!("{}:{}", host_name, remote_port).to_socket_addrs().await?;
-            for a in from_host_name {
-                candidate_remote_addrs.insert(a);
+            for remote_addr in remote_endpoint.address.iter().chain(remote_endpoint.host_name.iter()) {
+                for a in (remote_addr.as_str(), remote_port).to_socket_addrs().await? {
+                    if !remote_candidates.iter().any(|c| c.address == a) {
+                        remote_candidates.push(EndpointCandidate::new(a, candidate_source(remote_addr)));
+                    }
+                }
             }
         }
 
-        println!("Candidate Remote Endpoint Addresses: {:?}", candidate_remote_addrs).await;
+        // Gather local endpoint candidates - local endpoint is optional for initiating Connection.
+        // Without a port an ephemeral port is used, and without an address the wildcard address.
+        let mut local_candidates: Vec<EndpointCandidate> = vec![];
 
-        // Gather local endpoint candidates - local endpoint is optional for initiating Connection
-        let mut candidate_local_addrs = HashSet::new();
+        if let Some(local_endpoint) = self.local_endpoint.as_ref() {
+            let local_port = local_endpoint.port.unwrap_or(0);
 
-        if self.local_endpoint.is_some() {
-            let local_port = self.local_endpoint.as_ref().unwrap().port.as_ref().unwrap();
-            let local_addr = self.local_endpoint.as_ref().unwrap().address.as_ref().unwrap();
-            let local_socket_addr = format!("{}:{}", local_addr, local_port).to_socket_addrs().await?.next().unwrap();
-            candidate_local_addrs.insert(local_socket_addr);
+            match local_endpoint.address.as_ref() {
+                Some(local_addr) => {
+                    for a in (local_addr.as_str(), local_port).to_socket_addrs().await? {
+                        if !local_candidates.iter().any(|c| c.address == a) {
+                            local_candidates.push(EndpointCandidate::new(a, candidate_source(local_addr)));
+                        }
+                    }
+                },
+                None => {
+                    let ipv4_wildcard_addr = SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), local_port);
+                    let ipv6_wildcard_addr = SocketAddr::new(Ipv6Addr::UNSPECIFIED.into(), local_port);
+                    local_candidates.push(EndpointCandidate::new(ipv4_wildcard_addr, CandidateSource::Wildcard));
+                    local_candidates.push(EndpointCandidate::new(ipv6_wildcard_addr, CandidateSource::Wildcard));
+                },
+            }
         }
 
         // Gather protocol stack candidates
 
-        // Get the candidate protocol stacks available on the system, along with their ranks for connection racing
-        let candidate_protocol_ranks = self.calculate_candidate_protocol_ranks();
-
-        let candidate_protocol_ranks = match candidate_protocol_ranks {
-            Ok(r) => r,
-            Err(e) => return Err(e),
-        };
-
-        for (protocol, rank) in candidate_protocol_ranks.iter() {
-            println!("Candidate protocol stack: {} has rank {}", protocol, rank).await; 
-        }
+        // Get the candidate protocol stacks available on the system, along with their ranks for connection racing.
+        // Protocol stacks of equal rank are ordered by name, so that the order does not vary between calls.
+        let candidate_protocol_ranks = self.calculate_candidate_protocol_ranks()?;
 
-        let mut candidate_protocol_ranks_sorted: Vec<_> = candidate_protocol_ranks.iter().collect();
-        candidate_protocol_ranks_sorted.sort_by(|a, b| a.1.cmp(b.1).reverse());
+        let mut candidate_protocol_ranks_sorted: Vec<_> = candidate_protocol_ranks.into_iter().collect();
+        candidate_protocol_ranks_sorted.sort_by(|a, b| a.1.cmp(&b.1).reverse().then(a.0.cmp(b.0)));
 
         // Build candidate set for racing based on combinations of protocol stacks and local and remote IP addresses
 
-        let mut candidates: std::vec::Vec<(SocketAddr, Option<SocketAddr>, &str)> = vec![];
+        let mut candidates: Vec<Candidate> = vec![];
 
-        for protocol in candidate_protocol_ranks_sorted {
+        for (protocol, rank) in candidate_protocol_ranks_sorted {
             // Get IPv6 and IPv4 candidates for this protocol
-            let mut ipv6_candidates: std::vec::Vec<(SocketAddr, Option<SocketAddr>, &str)> = vec![];
-            let mut ipv4_candidates: std::vec::Vec<(SocketAddr, Option<SocketAddr>, &str)> = vec![];
+            let mut ipv6_candidates: Vec<Candidate> = vec![];
+            let mut ipv4_candidates: Vec<Candidate> = vec![];
 
-            for remote_addr in &candidate_remote_addrs {
-                let candidate_vec_ref;
-                if remote_addr.is_ipv6() {
-                    candidate_vec_ref = &mut ipv6_candidates;
-                } else {
-                    candidate_vec_ref = &mut ipv4_candidates;
-                }
+            for remote_candidate in &remote_candidates {
+                let candidate_vec_ref = match remote_candidate.family {
+                    AddressFamily::Ipv6 => &mut ipv6_candidates,
+                    AddressFamily::Ipv4 => &mut ipv4_candidates,
+                };
 
-                // Local endpoint supplied - include in candidate combinations
-                if !candidate_local_addrs.is_empty() {
-                    for local_addr in &candidate_local_addrs {
-                        candidate_vec_ref.push((*remote_addr, Some(*local_addr), protocol.0));
+                // Local endpoint supplied - include in candidate combinations of the same IP family
+                if self.local_endpoint.is_some() {
+                    for local_candidate in local_candidates.iter().filter(|c| c.family == remote_candidate.family) {
+                        candidate_vec_ref.push(Candidate {
+                            remote: remote_candidate.clone(),
+                            local: Some(local_candidate.clone()),
+                            protocol: protocol,
+                            rank: rank,
+                        });
                     }
 
                 // No local endpoints supplied - do not include in candidate combinations
                 } else {
-                    candidate_vec_ref.push((*remote_addr, None, protocol.0));
+                    candidate_vec_ref.push(Candidate {
+                        remote: remote_candidate.clone(),
+                        local: None,
+                        protocol: protocol,
+                        rank: rank,
+                    });
                 }
             }
 
@@ -212,9 +326,11 @@ impl<'a, T, U> Preconnection<'a, T, U> {
             candidates.extend(interleave(ipv6_candidates, ipv4_candidates));
         }
 
-        println!("\nFinal candidates: {:?}", candidates).await;
-
-        return Ok(candidates);
+        return Ok(ResolvedCandidates {
+            local: local_candidates,
+            remote: remote_candidates,
+            candidates: candidates,
+        });
     }
 
     pub fn calculate_candidate_protocol_ranks(&self) -> Result<HashMap<&'static str, u8>, TapsError> {
@@ -230,7 +346,7 @@ impl<'a, T, U> Preconnection<'a, T, U> {
         }
     
         'outer: for (candidate, service_levels) in candidate_protocols.iter() {
-            for (property, &preference_level) in self.transport_properties.unwrap().selection_properties.iter() {
+            for (property, &preference_level) in self.transport_properties.unwrap_or_default().selection_properties.iter() {
                 let property_service_level = service_levels[property];
     
                 match preference_level {
@@ -279,23 +395,180 @@ impl<'a, T, U> Preconnection<'a, T, U> {
     }
 }
 
-async fn attempt_connection(candidate: (SocketAddr, Option<SocketAddr>, &'static str), delay: Duration) -> Result<TransportInstance, TapsError> {
+// An endpoint address given as an IP address is used literally, anything else is looked up in DNS
+fn candidate_source(address: &str) -> CandidateSource {
+    match address.parse::<IpAddr>() {
+        Ok(_) => return CandidateSource::Literal,
+        Err(_) => return CandidateSource::Dns,
+    }
+}
+
+async fn attempt_connection(
+    candidate: Candidate,
+    trusted_certificates: Option<String>,
+    delay: Duration,
+) -> Result<TransportInstance, TapsError> {
     task::sleep(delay).await;
 
-    let (remote_addr, local_addr, protocol) = candidate;
+    let remote_addr = candidate.remote.address;
+    let local_addr = candidate.local.map(|local| local.address);
 
-    match protocol {
+    match candidate.protocol {
         "tcp" => return connect_tcp(remote_addr, local_addr).await,
-        "quic" => return connect_quic(remote_addr, local_addr).await,
+        "quic" => return connect_quic(remote_addr, local_addr, trusted_certificates.as_deref()).await,
         "udp" => return connect_udp(remote_addr, local_addr).await,
         _ => return Err(TapsError::ProtocolNotSupported),
     }
 }
 
-async fn connect_tcp(remote_addr: SocketAddr, _local_addr: Option<SocketAddr>) -> Result<TransportInstance, TapsError> {
-    println!("Attempting TCP connection to: {:?}", remote_addr).await;
+// How long each rendezvous attempt waits for the peer, and how often TCP connections are retried
+const RENDEZVOUS_TIMEOUT: Duration = Duration::from_secs(30);
+const RENDEZVOUS_RETRY_INTERVAL: Duration = Duration::from_millis(100);
+
+async fn attempt_rendezvous(
+    candidate: Candidate,
+    server_credentials: Option<(String, String)>,
+    trusted_certificates: Option<String>,
+    delay: Duration,
+) -> Result<TransportInstance, TapsError> {
+    task::sleep(delay).await;
+
+    let remote_addr = candidate.remote.address;
+    let local_addr = match candidate.local.as_ref() {
+        Some(local) => local.address,
+        None => return Err(TapsError::LocalEndpointNotProvided),
+    };
+
+    let attempt = async {
+        match candidate.protocol {
+            "tcp" => return rendezvous_tcp(remote_addr, local_addr).await,
+            "udp" => return rendezvous_udp(remote_addr, local_addr).await,
+            "quic" => return rendezvous_quic(remote_addr, local_addr, server_credentials, trusted_certificates).await,
+            _ => return Err(TapsError::ProtocolNotSupported),
+        }
+    };
+
+    match future::timeout(RENDEZVOUS_TIMEOUT, attempt).await {
+        Ok(result) => return result,
+        Err(_) => return Err(TapsError::ConnectionAttemptFailed),
+    }
+}
+
+// TCP simultaneous open. Connections to the peer are attempted repeatedly from the local port, which
+// is also listened on in case the peer's connection attempt arrives first. Either way the peers
+// share a single connection, as both ends use the same pair of addresses.
+async fn rendezvous_tcp(remote_addr: SocketAddr, local_addr: SocketAddr) -> Result<TransportInstance, TapsError> {
+    let listener = match listen_tcp_reuse_port(local_addr) {
+        Ok(listener) => listener,
+        Err(_) => return Err(TapsError::ConnectionAttemptFailed),
+    };
+
+    let accept = Box::pin(async {
+        loop {
+            if let Ok((stream, addr)) = listener.accept().await {
+                if addr == remote_addr {
+                    return stream;
+                }
+            }
+        }
+    });
+
+    let connect = Box::pin(async {
+        loop {
+            if let Ok(stream) = bind_and_connect_tcp(local_addr, remote_addr, true).await {
+                return stream;
+            }
+            task::sleep(RENDEZVOUS_RETRY_INTERVAL).await;
+        }
+    });
+
+    let stream = match select(accept, connect).await {
+        Either::Left((stream, _)) => stream,
+        Either::Right((stream, _)) => stream,
+    };
+
+    return Ok(TransportInstance {
+        tcp_stream_instance: Some(stream),
+        udp_socket_instance: None,
+        quic_stream_instance: None,
+    });
+}
+
+fn listen_tcp_reuse_port(local_addr: SocketAddr) -> io::Result<TcpListener> {
+    let socket = Socket::new(Domain::for_address(local_addr), Type::STREAM, Some(Protocol::TCP))?;
+    socket.set_reuse_address(true)?;
+    socket_options::set_reuse_port(&socket)?;
+    socket.set_nonblocking(true)?;
+    socket.bind(&local_addr.into())?;
+    socket.listen(1)?;
+
+    return Ok(TcpListener::from(std::net::TcpListener::from(socket)));
+}
+
+// UDP hole punching, by exchanging probes with the peer from the local port
+async fn rendezvous_udp(remote_addr: SocketAddr, local_addr: SocketAddr) -> Result<TransportInstance, TapsError> {
+    let socket = match UdpSocket::bind(local_addr).await {
+        Ok(socket) => socket,
+        Err(_) => return Err(TapsError::ConnectionAttemptFailed),
+    };
+    if socket.connect(remote_addr).await.is_err() {
+        return Err(TapsError::ConnectionAttemptFailed);
+    }
+
+    match UdpConnection::rendezvous(socket, remote_addr).await {
+        Ok(connection) => return Ok(TransportInstance {
+            tcp_stream_instance: None,
+            udp_socket_instance: Some(connection),
+            quic_stream_instance: None,
+        }),
+        Err(_) => return Err(TapsError::ConnectionAttemptFailed),
+    }
+}
+
+// QUIC needs a client and a server, so the peer with the lower port, or lower address if the ports
+// are the same, acts as the server. The server first sends an empty datagram to punch a hole for the
+// client's handshake, which the client ignores, while the client retransmits its handshake until
+// the server has started.
+async fn rendezvous_quic(
+    remote_addr: SocketAddr,
+    local_addr: SocketAddr,
+    server_credentials: Option<(String, String)>,
+    trusted_certificates: Option<String>,
+) -> Result<TransportInstance, TapsError> {
+    let is_server = (local_addr.port(), local_addr.ip()) < (remote_addr.port(), remote_addr.ip());
+    if !is_server {
+        return connect_quic(remote_addr, Some(local_addr), trusted_certificates.as_deref()).await;
+    }
+
+    let (certificate_chain, private_key) = match server_credentials {
+        Some(server_credentials) => server_credentials,
+        None => return Err(TapsError::InvalidSecurityParameters),
+    };
+
+    let socket = udp::bind_socket(local_addr)?;
+    socket.send_to(&[], remote_addr).await.ok();
+
+    let mut quic_listener = QuicListener::from_socket(socket, &certificate_chain, &private_key)?;
+    while let Some((quic_stream, addr)) = quic_listener.accept().await {
+        if addr == remote_addr {
+            return Ok(TransportInstance {
+                tcp_stream_instance: None,
+                udp_socket_instance: None,
+                quic_stream_instance: Some(quic_stream),
+            });
+        }
+    }
+
+    return Err(TapsError::ConnectionAttemptFailed);
+}
+
+pub(crate) async fn connect_tcp(remote_addr: SocketAddr, local_addr: Option<SocketAddr>) -> Result<TransportInstance, TapsError> {
+    println!("Attempting TCP connection from local address: {:?} to: {:?}", local_addr, remote_addr).await;
 
-    let stream = TcpStream::connect(remote_addr).await;
+    let stream = match local_addr {
+        Some(local_addr) => bind_and_connect_tcp(local_addr, remote_addr, false).await,
+        None => TcpStream::connect(remote_addr).await,
+    };
     let stream = match stream {
         Ok(stream) => stream,
         Err(_) => {println!("TCP connection attempt failed").await; return Err(TapsError::ConnectionAttemptFailed)},
@@ -308,14 +581,49 @@ async fn connect_tcp(remote_addr: SocketAddr, _local_addr: Option<SocketAddr>) -
     });
 }
 
-async fn connect_udp(remote_addr: SocketAddr, local_addr: Option<SocketAddr>) -> Result<TransportInstance, TapsError> {
-    if local_addr.is_none() {
-        return Err(TapsError::ConnectionAttemptFailed);
+// TcpStream::connect cannot bind to a local address first, so the socket is created and connected
+// without blocking, then handed to async-std once the handshake has completed. A rendezvous also
+// listens on the local port, which requires reuse_port.
+async fn bind_and_connect_tcp(local_addr: SocketAddr, remote_addr: SocketAddr, reuse_port: bool) -> io::Result<TcpStream> {
+    let socket = Socket::new(Domain::for_address(remote_addr), Type::STREAM, Some(Protocol::TCP))?;
+    socket.set_reuse_address(true)?;
+    if reuse_port {
+        socket_options::set_reuse_port(&socket)?;
+    }
+    socket.set_nonblocking(true)?;
+    socket.bind(&local_addr.into())?;
+
+    match socket.connect(&remote_addr.into()) {
+        Ok(_) => (),
+        Err(e) if socket_options::connect_in_progress(&e) => (),
+        Err(e) => return Err(e),
     }
 
-    println!("Attempting to create UDP socket bound to local address: {:?}, connected to remote address: {:?}", local_addr, remote_addr).await;
+    let stream = Async::new(std::net::TcpStream::from(socket))?;
+    stream.writable().await?;
+    if let Some(e) = stream.get_ref().take_error()? {
+        return Err(e);
+    }
+
+    return Ok(TcpStream::from(stream.into_inner()?));
+}
+
+// Bind to INADDR_ANY or IN6ADDR_ANY depending on the IP family of the remote address.
+// This is needed on macOS and BSD variants that don't support binding to IN6ADDR_ANY for both v4 and v6.
+fn wildcard_addr(remote_addr: SocketAddr) -> SocketAddr {
+    match remote_addr {
+        SocketAddr::V4(_) => return "0.0.0.0:0".parse().unwrap(),
+        SocketAddr::V6(_) => return "[::]:0".parse().unwrap(),
+    }
+}
 
-    let socket = UdpSocket::bind(local_addr.unwrap()).await;
+pub(crate) async fn connect_udp(remote_addr: SocketAddr, local_addr: Option<SocketAddr>) -> Result<TransportInstance, TapsError> {
+    // Use an ephemeral port on any local address if no local endpoint was given
+    let bind_addr = local_addr.unwrap_or(wildcard_addr(remote_addr));
+
+    println!("Attempting to create UDP socket bound to local address: {:?}, connected to remote address: {:?}", bind_addr, remote_addr).await;
+
+    let socket = UdpSocket::bind(bind_addr).await;
     let socket = match socket {
         Ok(socket) => socket,
         Err(_) => {println!("UDP connection attempt failed").await; return Err(TapsError::ConnectionAttemptFailed)},
@@ -329,142 +637,69 @@ async fn connect_udp(remote_addr: SocketAddr, local_addr: Option<SocketAddr>) ->
 
     return Ok(TransportInstance {
         tcp_stream_instance: None,
-        udp_socket_instance: Some(socket),
+        udp_socket_instance: Some(UdpConnection::connected(socket, remote_addr)),
         quic_stream_instance: None,
     });
 }
 
-async fn connect_quic(remote_addr: SocketAddr, local_addr: Option<SocketAddr>) -> Result<TransportInstance, TapsError> {
-
-    return task::spawn_blocking(move || -> Result<TransportInstance, TapsError> {
-        let mut buf = [0; 65535];
-        let mut out = [0; QUIC_MAX_DATAGRAM_SIZE];
-
-        // Setup the event loop.
-        let poll = mio::Poll::new().unwrap();
-        let mut events = mio::Events::with_capacity(1024);
-
-        let bind_addr: SocketAddr;
-        if local_addr.is_none() {
-            // Bind to INADDR_ANY or IN6ADDR_ANY depending on the IP family of the
-            // server address. This is needed on macOS and BSD variants that don't
-            // support binding to IN6ADDR_ANY for both v4 and v6.
-            bind_addr = match remote_addr {
-                std::net::SocketAddr::V4(_) => "0.0.0.0:0".parse().unwrap(),
-                std::net::SocketAddr::V6(_) => "[::]:0".parse().unwrap(),
-            };  
-        } else {
-            bind_addr = local_addr.unwrap();
-        }
+async fn connect_quic(
+    remote_addr: SocketAddr,
+    local_addr: Option<SocketAddr>,
+    trusted_certificates: Option<&str>,
+) -> Result<TransportInstance, TapsError> {
+    let bind_addr = local_addr.unwrap_or(wildcard_addr(remote_addr));
 
-        // Create the UDP socket backing the QUIC connection, and register it with
-        // the event loop.
-        let socket = std::net::UdpSocket::bind(bind_addr).unwrap();
-        socket.connect(remote_addr).unwrap();
-
-        let socket = mio::net::UdpSocket::from_socket(socket).unwrap();
-        poll.register(
-            &socket,
-            mio::Token(0),
-            mio::Ready::readable(),
-            mio::PollOpt::edge(),
-        )
-        .unwrap();
-
-        // Create the configuration for the QUIC connection.
-        let mut config = quiche::Config::new(quiche::PROTOCOL_VERSION).unwrap();
-
-        // Generate a random source connection ID for the connection.
-        let mut scid = [0; quiche::MAX_CONN_ID_LEN];
-        SystemRandom::new().fill(&mut scid[..]).unwrap();
-
-        let mut conn = quiche::connect(None, &scid, &mut config).unwrap();
-        
-        std::println!(
-            "QUIC connection attempt: connecting from {:} to {:} ",
-            socket.local_addr().unwrap(),
-            remote_addr,
-        );
-
-        // initial send
-        let write = conn.send(&mut out).unwrap();
-        while let Err(e) = socket.send(&out[..write]) {
-            if e.kind() == std::io::ErrorKind::WouldBlock {
-                // send() would block
-                continue;
-            }
-            // TODO: this error return should include "e"
-            return Err(TapsError::ConnectionAttemptFailed);
+    // Create the UDP socket backing the QUIC connection
+    let socket = UdpSocket::bind(bind_addr).await;
+    let socket = match socket {
+        Ok(socket) => socket,
+        Err(_) => {println!("QUIC connection attempt failed").await; return Err(TapsError::ConnectionAttemptFailed)},
+    };
+
+    if socket.connect(remote_addr).await.is_err() {
+        println!("QUIC connection attempt failed").await;
+        return Err(TapsError::ConnectionAttemptFailed);
+    }
+
+    socket_options::set_dont_fragment(&socket, bind_addr, true).ok();
+
+    // Create the configuration for the QUIC connection.
+    let mut config = quic::new_config()?;
+
+    // The server's certificate may be issued by a certificate authority which is not trusted by default
+    if let Some(trusted_certificates) = trusted_certificates {
+        if config.load_verify_locations_from_file(trusted_certificates).is_err() {
+            return Err(TapsError::InvalidSecurityParameters);
         }
+    }
 
-        loop {
-            poll.poll(&mut events, conn.timeout()).unwrap();
-    
-            // Read incoming UDP packets from the socket and feed them to quiche,
-            // until there are no more packets to read.
-            'read: loop {
-                // If the event loop reported no events, it means that the timeout
-                // has expired, so handle it without attempting to read packets. We
-                // will then proceed with the send loop.
-                if events.is_empty() {    
-                    conn.on_timeout();
-                    break 'read;
-                }
-    
-                let len = match socket.recv(&mut buf) {
-                    Ok(v) => v,
-                    Err(e) => {
-                        // There are no more UDP packets to read, so end the read
-                        // loop.
-                        if e.kind() == std::io::ErrorKind::WouldBlock {
-                            break 'read;
-                        }
-    
-                        return Err(TapsError::ConnectionAttemptFailed);
-                    },
-                };
-    
-                // Process potentially coalesced packets.
-                match conn.recv(&mut buf[..len]) {
-                    Ok(v) => v,
-    
-                    Err(_) => {
-                        continue 'read;
-                    },
-                };    
-            }
-    
-            if conn.is_established() {
-                return Ok(TransportInstance {
-                    tcp_stream_instance: None,
-                    udp_socket_instance: None,
-                    quic_stream_instance: Some(conn),
-                });
-            }
+    // Generate a random source connection ID for the connection.
+    let mut scid = [0; quiche::MAX_CONN_ID_LEN];
+    SystemRandom::new().fill(&mut scid[..]).unwrap();
 
-            // Generate outgoing QUIC packets and send them on the UDP socket, until
-            // quiche reports that there are no more packets to be sent.
-            loop {
-                let write = match conn.send(&mut out) {
-                    Ok(v) => v,
+    let conn = match quiche::connect(None, &scid, &mut config) {
+        Ok(conn) => conn,
+        Err(_) => return Err(TapsError::ConnectionAttemptFailed),
+    };
 
-                    Err(quiche::Error::Done) => {
-                        break;
-                    },
+    println!(
+        "QUIC connection attempt: connecting from {:?} to {:?}",
+        socket.local_addr(),
+        remote_addr,
+    ).await;
 
-                    Err(_) => {
-                        // TODO: handle error
-                        conn.close(false, 0x1, b"fail").ok();
-                        break;
-                    },
-                };
+    // The connection's driver performs the handshake on the async socket. If this attempt loses the
+    // race and is dropped, the driver closes the connection once its handle has been dropped.
+    let connection = QuicConnection::spawn(conn, socket, remote_addr);
 
-                if let Err(e) = socket.send(&out[..write]) {
-                    if e.kind() == std::io::ErrorKind::WouldBlock {
-                        break;
-                    }
-                }
-            }
-        }
-    }).await;
+    if connection.established().await.is_err() {
+        println!("QUIC connection attempt failed").await;
+        return Err(TapsError::ConnectionAttemptFailed);
+    }
+
+    return Ok(TransportInstance {
+        tcp_stream_instance: None,
+        udp_socket_instance: None,
+        quic_stream_instance: Some(connection.stream(0)),
+    });
 }
@@ -0,0 +1,872 @@
+use crate::error::TapsError;
+use crate::connection_event::ConnectionEvent;
+use crate::socket_options;
+use crate::udp;
+
+use std::collections::{HashMap, HashSet};
+use std::net::{IpAddr, SocketAddr};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use async_std::{
+    future,
+    net::UdpSocket,
+    task,
+    task::{Poll, Waker},
+};
+
+use futures::channel::mpsc::{unbounded, UnboundedReceiver, UnboundedSender};
+use futures::channel::oneshot;
+use futures::future::{poll_fn, select, Either, FutureExt};
+use futures::stream::StreamExt;
+use ring::hmac;
+use ring::rand::*;
+
+// Largest UDP payload of a QUIC packet. QUIC packets must not be fragmented at the IP layer, so every
+// socket used for QUIC sets the don't fragment flag, and packets are kept small enough for common paths.
+pub(crate) const QUIC_MAX_DATAGRAM_SIZE: usize = 1350;
+
+// The ALPN identifier of messages exchanged over QUIC streams, in wire format
+const APPLICATION_PROTOCOLS: &[u8] = b"\x07rs-taps";
+
+// Transport error code sent to a peer whose connection has been refused by a listener
+const CONNECTION_REFUSED: u64 = 0x2;
+
+// Lowest priority of a QUIC stream, and the number of message priorities mapped onto each urgency
+const MAX_URGENCY: u8 = 7;
+const PRIORITY_BAND: u32 = 32;
+
+// How long a client may take to answer a stateless retry
+const RETRY_TOKEN_LIFETIME: Duration = Duration::from_secs(10);
+
+// Inputs to a QUIC connection's driver task, in addition to its timers
+pub(crate) enum DriverEvent {
+    Datagram(Vec<u8>, SocketAddr),
+    Wake,
+}
+
+enum DriverInput {
+    Event(DriverEvent),
+    SoftError,
+    Timeout,
+    Abandoned,
+}
+
+struct QuicState {
+    conn: Pin<Box<quiche::Connection>>,
+    wakers: Vec<Waker>,
+    // The event stream of the Connection using each stream
+    event_senders: HashMap<u64, UnboundedSender<ConnectionEvent>>,
+    closed_locally: bool,
+    failed: bool,
+    abort_timeout: Option<Duration>,
+    last_received: Instant,
+    is_server: bool,
+    next_stream_id: u64,
+    // Streams which have been used by a Connection, and those still in use
+    claimed_streams: HashSet<u64>,
+    open_streams: HashSet<u64>,
+}
+
+impl QuicState {
+    fn emit(&mut self, event: impl Fn() -> ConnectionEvent) {
+        self.event_senders.retain(|_, sender| sender.unbounded_send(event()).is_ok());
+    }
+
+    fn wake_all(&mut self) {
+        for waker in self.wakers.drain(..) {
+            waker.wake();
+        }
+    }
+
+    // Bidirectional streams opened by the peer have IDs ending 0b00 at a server, and 0b01 at a client
+    fn is_peer_bidi_stream(&self, stream_id: u64) -> bool {
+        let initiator = if self.is_server { 0 } else { 1 };
+        return stream_id % 4 == initiator;
+    }
+}
+
+// Handle to a QUIC connection. The UDP socket and event loop of the connection are owned by a
+// background driver task, which sends quiche's output, feeds incoming datagrams back in and fires timeouts.
+#[derive(Clone)]
+pub struct QuicConnection {
+    state: Arc<Mutex<QuicState>>,
+    driver: UnboundedSender<DriverEvent>,
+    socket: Arc<UdpSocket>,
+    demultiplexed: bool,
+}
+
+// A single stream of a QUIC connection, used as the transport instance of a Connection. Connections
+// cloned from one another use separate streams of the same QUIC connection.
+pub struct QuicStream {
+    pub connection: QuicConnection,
+    pub stream_id: u64,
+}
+
+// A connection is closed once none of its streams are in use. A connection accepted by a QuicListener
+// is also held by the listener's task, so it would otherwise stay open until it timed out.
+impl Drop for QuicStream {
+    fn drop(&mut self) {
+        if !self.connection.release_stream(self.stream_id) {
+            self.connection.abandon();
+        }
+    }
+}
+
+// Accepts QUIC connections on a UDP socket. Incoming packets are demultiplexed by connection ID
+// in a background task, and connections are yielded once their handshake has completed.
+pub struct QuicListener {
+    incoming: UnboundedReceiver<(QuicStream, SocketAddr)>,
+    backlog: udp::Backlog,
+    // Dropped with the listener, telling the background task to stop accepting new connections
+    _closed: oneshot::Sender<()>,
+}
+
+// Configuration shared by all QUIC connections
+pub(crate) fn new_config() -> Result<quiche::Config, TapsError> {
+    let mut config = match quiche::Config::new(quiche::PROTOCOL_VERSION) {
+        Ok(config) => config,
+        Err(_) => return Err(TapsError::ConnectionAttemptFailed),
+    };
+
+    config.set_max_idle_timeout(30000);
+    config.set_max_udp_payload_size(QUIC_MAX_DATAGRAM_SIZE as u64);
+    config.set_initial_max_data(10_000_000);
+    config.set_initial_max_stream_data_bidi_local(1_000_000);
+    config.set_initial_max_stream_data_bidi_remote(1_000_000);
+    config.set_initial_max_stream_data_uni(1_000_000);
+    config.set_initial_max_streams_bidi(100);
+    config.set_initial_max_streams_uni(100);
+    config.set_disable_active_migration(true);
+
+    // TLS for QUIC requires an application protocol to be negotiated, and both peers use this library
+    if config.set_application_protos(APPLICATION_PROTOCOLS).is_err() {
+        return Err(TapsError::ConnectionAttemptFailed);
+    }
+
+    return Ok(config);
+}
+
+impl QuicConnection {
+    // Start driving a connection whose packets are read directly from its own socket, which must
+    // already be connected to the peer
+    pub(crate) fn spawn(conn: Pin<Box<quiche::Connection>>, socket: UdpSocket, peer: SocketAddr) -> QuicConnection {
+        return QuicConnection::start(conn, Arc::new(socket), peer, true);
+    }
+
+    // Start driving a connection which shares its socket with other connections. Incoming packets
+    // for the connection must be passed to `deliver` by the task reading the socket.
+    pub(crate) fn spawn_demultiplexed(conn: Pin<Box<quiche::Connection>>, socket: Arc<UdpSocket>, peer: SocketAddr) -> QuicConnection {
+        return QuicConnection::start(conn, socket, peer, false);
+    }
+
+    fn start(conn: Pin<Box<quiche::Connection>>, socket: Arc<UdpSocket>, peer: SocketAddr, read_socket: bool) -> QuicConnection {
+        let (sender, receiver) = unbounded();
+
+        // Only connections accepted by a QuicListener share their socket
+        let is_server = !read_socket;
+        let state = Arc::new(Mutex::new(QuicState {
+            conn: conn,
+            wakers: vec![],
+            event_senders: HashMap::new(),
+            closed_locally: false,
+            failed: false,
+            abort_timeout: None,
+            last_received: Instant::now(),
+            is_server: is_server,
+            // Stream 0 is the first stream opened by the client, and the first a server opens is stream 1
+            next_stream_id: if is_server { 1 } else { 4 },
+            claimed_streams: HashSet::new(),
+            open_streams: HashSet::new(),
+        }));
+
+        task::spawn(drive(state.clone(), socket.clone(), peer, receiver, read_socket));
+
+        return QuicConnection {
+            state: state,
+            driver: sender,
+            socket: socket,
+            demultiplexed: !read_socket,
+        };
+    }
+
+    // Pass a datagram received on a shared socket to the driver. Returns false if the driver has finished.
+    pub(crate) fn deliver(&self, datagram: Vec<u8>, from: SocketAddr) -> bool {
+        return self.driver.unbounded_send(DriverEvent::Datagram(datagram, from)).is_ok();
+    }
+
+    // Deliver asynchronous events from the driver, such as the connection being closed by the peer
+    // Replaces any event stream previously subscribed for the stream
+    pub(crate) fn subscribe(&self, stream_id: u64, event_sender: UnboundedSender<ConnectionEvent>) {
+        self.state.lock().unwrap().event_senders.insert(stream_id, event_sender);
+    }
+
+    fn wake_driver(&self) {
+        self.driver.unbounded_send(DriverEvent::Wake).ok();
+    }
+
+    // Mark all packets of the connection with a DSCP codepoint. Packets on a socket shared with other
+    // connections can only use the default marking.
+    pub(crate) fn set_dscp(&self, dscp: u8) -> Result<(), TapsError> {
+        if self.demultiplexed {
+            if dscp == 0 {
+                return Ok(());
+            }
+            return Err(TapsError::ConnectionPropertyNotSupported);
+        }
+
+        let local_addr = self.socket.local_addr()?;
+        if socket_options::set_dscp(&*self.socket, local_addr, dscp).is_err() {
+            return Err(TapsError::ConnectionPropertyNotSupported);
+        }
+        return Ok(());
+    }
+
+    // Close a connection which is no longer used, without waiting for the close handshake. Does
+    // nothing if the connection is already being closed.
+    fn abandon(&self) {
+        self.state.lock().unwrap().conn.close(false, 0x0, b"").ok();
+        self.wake_driver();
+    }
+
+    // Close a connection refused by a listener. quiche only sends an application close in 1-RTT
+    // packets, which may not be sent so soon after the handshake, so a transport error is used.
+    pub(crate) fn refuse(&self) {
+        self.state.lock().unwrap().conn.close(false, CONNECTION_REFUSED, b"").ok();
+        self.wake_driver();
+    }
+
+    // Abort the connection if nothing is received from the peer for longer than the timeout
+    pub(crate) fn set_abort_timeout(&self, abort_timeout: Option<Duration>) {
+        self.state.lock().unwrap().abort_timeout = abort_timeout;
+        self.wake_driver();
+    }
+
+    // Use a stream of the connection for a Connection
+    pub(crate) fn stream(&self, stream_id: u64) -> QuicStream {
+        let mut state = self.state.lock().unwrap();
+        state.claimed_streams.insert(stream_id);
+        state.open_streams.insert(stream_id);
+
+        return QuicStream {
+            connection: self.clone(),
+            stream_id: stream_id,
+        };
+    }
+
+    // Open a new bidirectional stream. The peer learns of the stream once data is sent on it.
+    pub(crate) fn open_stream(&self) -> QuicStream {
+        let stream_id = {
+            let mut state = self.state.lock().unwrap();
+            let stream_id = state.next_stream_id;
+            state.next_stream_id += 4;
+            stream_id
+        };
+
+        return self.stream(stream_id);
+    }
+
+    // Wait for the peer to open a new bidirectional stream. Returns None once the connection has
+    // closed, or once no streams of the connection remain in use.
+    pub(crate) async fn accept_stream(&self) -> Option<QuicStream> {
+        let stream_id = poll_fn(|cx| {
+            let mut state = self.state.lock().unwrap();
+
+            if state.conn.is_closed() || state.open_streams.is_empty() {
+                return Poll::Ready(None);
+            }
+
+            let new_stream = state.conn.readable()
+                .find(|id| state.is_peer_bidi_stream(*id) && !state.claimed_streams.contains(id));
+
+            if let Some(stream_id) = new_stream {
+                return Poll::Ready(Some(stream_id));
+            }
+            state.wakers.push(cx.waker().clone());
+            return Poll::Pending;
+        }).await?;
+
+        return Some(self.stream(stream_id));
+    }
+
+    // Stop using a stream, returning true if other streams of the connection are still in use
+    fn release_stream(&self, stream_id: u64) -> bool {
+        if let Ok(mut state) = self.state.lock() {
+            state.open_streams.remove(&stream_id);
+            state.wake_all();
+            return !state.open_streams.is_empty();
+        }
+        return false;
+    }
+
+    pub fn is_established(&self) -> bool {
+        return self.state.lock().unwrap().conn.is_established();
+    }
+
+    pub fn is_closed(&self) -> bool {
+        return self.state.lock().unwrap().conn.is_closed();
+    }
+
+    // Wait until the handshake has completed
+    pub async fn established(&self) -> Result<(), TapsError> {
+        return poll_fn(|cx| {
+            let mut state = self.state.lock().unwrap();
+
+            if state.conn.is_established() {
+                return Poll::Ready(Ok(()));
+            }
+            if state.conn.is_closed() {
+                return Poll::Ready(Err(TapsError::ConnectionAttemptFailed));
+            }
+            state.wakers.push(cx.waker().clone());
+            return Poll::Pending;
+        }).await;
+    }
+
+    // Lower urgency streams are sent first. The priority of a message is mapped onto an urgency by priority_urgency.
+    pub fn stream_priority(&self, stream_id: u64, priority: u32) -> Result<(), TapsError> {
+        match self.state.lock().unwrap().conn.stream_priority(stream_id, priority_urgency(priority), true) {
+            Ok(_) => return Ok(()),
+            Err(_) => return Err(TapsError::MessageSendFailed),
+        }
+    }
+
+    // Queue data on a stream, waiting for flow control credit if necessary. If a lifetime is given
+    // and none of the data could be queued within it, the data expires instead.
+    pub async fn stream_send(&self, stream_id: u64, data: &[u8], fin: bool, lifetime: Option<Duration>) -> Result<(), TapsError> {
+        let mut written = 0;
+        let mut expiry = lifetime.map(|lifetime| Box::pin(task::sleep(lifetime)));
+
+        return poll_fn(|cx| {
+            let mut state = self.state.lock().unwrap();
+
+            loop {
+                if state.conn.is_closed() {
+                    return Poll::Ready(Err(TapsError::MessageSendFailed));
+                }
+
+                match state.conn.stream_send(stream_id, &data[written..], fin) {
+                    Ok(v) => {
+                        written += v;
+                        if written == data.len() {
+                            self.wake_driver();
+                            return Poll::Ready(Ok(()));
+                        }
+                    },
+                    Err(quiche::Error::Done) => {
+                        // Data already queued cannot be withdrawn from the stream
+                        if written == 0 {
+                            if let Some(expiry) = expiry.as_mut() {
+                                if expiry.poll_unpin(cx).is_ready() {
+                                    return Poll::Ready(Err(TapsError::MessageExpired));
+                                }
+                            }
+                        }
+                        state.wakers.push(cx.waker().clone());
+                        self.wake_driver();
+                        return Poll::Pending;
+                    },
+                    Err(_) => return Poll::Ready(Err(TapsError::MessageSendFailed)),
+                }
+            }
+        }).await;
+    }
+
+    // Read data from a stream, waiting until some is available
+    pub async fn stream_recv(&self, stream_id: u64, buf: &mut [u8]) -> Result<(usize, bool), TapsError> {
+        return poll_fn(|cx| {
+            let mut state = self.state.lock().unwrap();
+
+            match state.conn.stream_recv(stream_id, buf) {
+                Ok((read, fin)) => {
+                    // Reading may have opened up flow control credit to be sent to the peer
+                    self.wake_driver();
+                    return Poll::Ready(Ok((read, fin)));
+                },
+                // Stream has no data yet, or has not yet been opened by the peer
+                Err(quiche::Error::Done) | Err(quiche::Error::InvalidStreamState) if !state.conn.is_closed() => {
+                    state.wakers.push(cx.waker().clone());
+                    return Poll::Pending;
+                },
+                Err(_) => return Poll::Ready(Err(TapsError::MessageReceiveFailed)),
+            }
+        }).await;
+    }
+
+    // Finish sending on a stream. The connection itself is closed when its last stream in use is closed.
+    pub async fn close_stream(&self, stream_id: u64) -> Result<(), TapsError> {
+        if !self.release_stream(stream_id) {
+            return self.close().await;
+        }
+
+        match self.state.lock().unwrap().conn.stream_send(stream_id, b"", true) {
+            Ok(_) | Err(quiche::Error::Done) | Err(quiche::Error::FinalSize) => (),
+            Err(_) => return Err(TapsError::ConnectionCloseFailed),
+        }
+        self.wake_driver();
+
+        return Ok(());
+    }
+
+    // Send CONNECTION_CLOSE to the peer and wait until the close handshake has completed. As in refuse,
+    // a transport close is used, here with no error, as quiche may not yet send an application close.
+    pub async fn close(&self) -> Result<(), TapsError> {
+        {
+            let mut state = self.state.lock().unwrap();
+            match state.conn.close(false, 0x0, b"") {
+                Ok(_) | Err(quiche::Error::Done) => (),
+                Err(_) => return Err(TapsError::ConnectionCloseFailed),
+            }
+            state.closed_locally = true;
+        }
+
+        self.wake_driver();
+
+        poll_fn(|cx| {
+            let mut state = self.state.lock().unwrap();
+
+            if state.conn.is_closed() {
+                return Poll::Ready(());
+            }
+            state.wakers.push(cx.waker().clone());
+            return Poll::Pending;
+        }).await;
+
+        return Ok(());
+    }
+}
+
+impl QuicListener {
+    pub(crate) async fn bind(local_addr: SocketAddr, certificate_chain: &str, private_key: &str) -> Result<QuicListener, TapsError> {
+        let socket = udp::bind_socket(local_addr)?;
+        return QuicListener::from_socket(socket, certificate_chain, private_key);
+    }
+
+    // Accept connections on a socket which has already been bound
+    pub(crate) fn from_socket(socket: UdpSocket, certificate_chain: &str, private_key: &str) -> Result<QuicListener, TapsError> {
+        let mut config = new_config()?;
+
+        if config.load_cert_chain_from_pem_file(certificate_chain).is_err() {
+            return Err(TapsError::InvalidSecurityParameters);
+        }
+        if config.load_priv_key_from_pem_file(private_key).is_err() {
+            return Err(TapsError::InvalidSecurityParameters);
+        }
+
+        let (sender, receiver) = unbounded();
+
+        socket_options::set_dont_fragment(&socket, socket.local_addr()?, true).ok();
+
+        let (closed_sender, closed) = oneshot::channel();
+        let backlog = udp::Backlog::new();
+        task::spawn(accept_connections(Arc::new(socket), config, sender, backlog.clone(), closed));
+
+        return Ok(QuicListener {
+            incoming: receiver,
+            backlog: backlog,
+            _closed: closed_sender,
+        });
+    }
+
+    // Wait for the next connection to complete its handshake
+    pub async fn accept(&mut self) -> Option<(QuicStream, SocketAddr)> {
+        let incoming_conn = self.incoming.next().await?;
+        self.backlog.release();
+        return Some(incoming_conn);
+    }
+}
+
+// Connections in the backlog are closed along with the listener, as are those whose handshake
+// completes afterwards, since they can no longer be accepted
+impl Drop for QuicListener {
+    fn drop(&mut self) {
+        self.backlog.close();
+        self.incoming.close();
+        while let Ok(_) = self.incoming.try_recv() {}
+    }
+}
+
+async fn accept_connections(
+    socket: Arc<UdpSocket>,
+    mut config: quiche::Config,
+    new_connections: UnboundedSender<(QuicStream, SocketAddr)>,
+    backlog: udp::Backlog,
+    closed: oneshot::Receiver<()>,
+) {
+    let mut buf = [0; 65535];
+    let mut out = [0; QUIC_MAX_DATAGRAM_SIZE];
+    let mut clients = HashMap::<Vec<u8>, QuicConnection>::new();
+
+    let rng = SystemRandom::new();
+    let conn_id_seed = match hmac::Key::generate(hmac::HMAC_SHA256, &rng) {
+        Ok(key) => key,
+        Err(_) => return,
+    };
+
+    let mut listener_closed = Some(closed);
+
+    loop {
+        // Once the listener is closed, packets are still passed to the connections it accepted,
+        // until they have all been closed too, but no new connections are accepted
+        let received = match listener_closed.take() {
+            Some(closed) => match select(Box::pin(socket.recv_from(&mut buf)), closed).await {
+                Either::Left((received, closed)) => {
+                    listener_closed = Some(closed);
+                    received
+                },
+                Either::Right(_) => continue,
+            },
+            None => {
+                clients.retain(|_, c| !c.is_closed());
+                if clients.is_empty() {
+                    return;
+                }
+                socket.recv_from(&mut buf).await
+            },
+        };
+
+        let (len, src) = match received {
+            Ok(v) => v,
+            Err(_) => continue,
+        };
+
+        let hdr = match quiche::Header::from_slice(&mut buf[..len], quiche::MAX_CONN_ID_LEN) {
+            Ok(v) => v,
+            Err(_) => continue,
+        };
+
+        let conn_id = hmac::sign(&conn_id_seed, &hdr.dcid);
+        let conn_id = &conn_id.as_ref()[..quiche::MAX_CONN_ID_LEN];
+
+        let client = match clients.get(&hdr.dcid).or_else(|| clients.get(conn_id)) {
+            Some(client) => client.clone(),
+            None => {
+                if hdr.ty != quiche::Type::Initial || listener_closed.is_none() {
+                    continue;
+                }
+
+                if !quiche::version_is_supported(hdr.version) {
+                    if let Ok(len) = quiche::negotiate_version(&hdr.scid, &hdr.dcid, &mut out) {
+                        socket.send_to(&out[..len], src).await.ok();
+                    }
+                    continue;
+                }
+
+                let token = hdr.token.as_ref().map(|t| t.as_slice()).unwrap_or(&[]);
+
+                // Do stateless retry if the client didn't send a token, so that the client's address is validated
+                if token.is_empty() {
+                    let new_token = mint_token(&conn_id_seed, &hdr.dcid, &src);
+
+                    if let Ok(len) = quiche::retry(&hdr.scid, &hdr.dcid, conn_id, &new_token, hdr.version, &mut out) {
+                        socket.send_to(&out[..len], src).await.ok();
+                    }
+                    continue;
+                }
+
+                let odcid = match validate_token(&conn_id_seed, &src, token) {
+                    Some(odcid) => odcid,
+                    None => continue,
+                };
+
+                if hdr.dcid.len() != quiche::MAX_CONN_ID_LEN {
+                    continue;
+                }
+
+                // No handshake is started while the backlog is full, the client retries its Initial later
+                if !backlog.try_reserve() {
+                    continue;
+                }
+
+                let conn = match quiche::accept(&hdr.dcid, Some(odcid), &mut config) {
+                    Ok(conn) => conn,
+                    Err(_) => {
+                        backlog.release();
+                        continue;
+                    },
+                };
+
+                // Forget connections whose drivers have finished
+                clients.retain(|_, c| !c.is_closed());
+
+                let client = QuicConnection::spawn_demultiplexed(conn, socket.clone(), src);
+                clients.insert(hdr.dcid.clone(), client.clone());
+
+                // Yield the connection's first stream once its handshake completes, followed by
+                // any streams the client opens for Connections cloned from the first. Each takes a
+                // place in the backlog, and the client is already using any stream it opens, so
+                // streams opened while the backlog is full wait for room.
+                let established = client.clone();
+                let new_connections = new_connections.clone();
+                let backlog = backlog.clone();
+                task::spawn(async move {
+                    if established.established().await.is_err() {
+                        backlog.release();
+                        return;
+                    }
+                    if new_connections.unbounded_send((established.stream(0), src)).is_err() {
+                        return;
+                    }
+                    while let Some(stream) = established.accept_stream().await {
+                        if !backlog.reserve().await {
+                            return;
+                        }
+                        if new_connections.unbounded_send((stream, src)).is_err() {
+                            return;
+                        }
+                    }
+                });
+
+                client
+            },
+        };
+
+        if !client.deliver(buf[..len].to_vec(), src) {
+            clients.remove(&hdr.dcid);
+        }
+    }
+}
+
+// QUIC stream urgencies range from 0 to 7, so message priorities are mapped onto them in bands of 32,
+// keeping lower priority values first. The default priority of 100 has quiche's default urgency of 3,
+// and all priorities of 224 and above share the last urgency.
+fn priority_urgency(priority: u32) -> u8 {
+    return std::cmp::min(priority / PRIORITY_BAND, MAX_URGENCY as u32) as u8;
+}
+
+// Generate an address validation token for stateless retry, carrying an expiry time and the
+// original destination connection ID. The token is signed together with the client's IP address, so
+// that it cannot be forged for a spoofed source address. The port is not signed, as a NAT may change
+// it between the client's packets.
+fn mint_token(key: &hmac::Key, odcid: &[u8], src: &SocketAddr) -> Vec<u8> {
+    let expiry = SystemTime::now() + RETRY_TOKEN_LIFETIME;
+    let expiry = expiry.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+
+    let mut token = Vec::new();
+    token.extend_from_slice(&expiry.to_be_bytes());
+    token.extend_from_slice(odcid);
+
+    let tag = hmac::sign(key, &retry_token_signed_data(&token, src));
+    token.extend_from_slice(tag.as_ref());
+
+    return token;
+}
+
+// Check the signature and expiry of a token minted by mint_token, returning the original
+// destination connection ID
+fn validate_token<'a>(key: &hmac::Key, src: &SocketAddr, token: &'a [u8]) -> Option<&'a [u8]> {
+    let tag_len = hmac::HMAC_SHA256.digest_algorithm().output_len;
+    if token.len() < 8 + tag_len {
+        return None;
+    }
+
+    let (contents, tag) = token.split_at(token.len() - tag_len);
+    if hmac::verify(key, &retry_token_signed_data(contents, src), tag).is_err() {
+        return None;
+    }
+
+    let mut expiry = [0; 8];
+    expiry.copy_from_slice(&contents[..8]);
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+    if now > u64::from_be_bytes(expiry) {
+        return None;
+    }
+
+    return Some(&contents[8..]);
+}
+
+fn retry_token_signed_data(contents: &[u8], src: &SocketAddr) -> Vec<u8> {
+    // The key is shared with connection ID generation, so the data is labelled to keep the two apart
+    let mut data = b"rs_taps retry token".to_vec();
+
+    match src.ip() {
+        IpAddr::V4(a) => data.extend_from_slice(&a.octets()),
+        IpAddr::V6(a) => data.extend_from_slice(&a.octets()),
+    };
+
+    data.extend_from_slice(contents);
+    return data;
+}
+
+// Event loop of a single QUIC connection, runs until the connection is closed
+async fn drive(
+    state: Arc<Mutex<QuicState>>,
+    socket: Arc<UdpSocket>,
+    mut peer: SocketAddr,
+    mut events: UnboundedReceiver<DriverEvent>,
+    read_socket: bool,
+) {
+    let mut out = [0; QUIC_MAX_DATAGRAM_SIZE];
+    let mut abandoned = false;
+
+    loop {
+        // Generate outgoing QUIC packets and send them on the UDP socket, until
+        // quiche reports that there are no more packets to be sent.
+        loop {
+            let write = {
+                let mut state = state.lock().unwrap();
+                match state.conn.send(&mut out) {
+                    Ok(v) => v,
+                    Err(quiche::Error::Done) => break,
+                    Err(_) => {
+                        state.conn.close(false, 0x1, b"fail").ok();
+                        state.failed = true;
+                        break;
+                    },
+                }
+            };
+
+            // A driver reading its own socket has connected it to the peer, and BSD and macOS reject
+            // sendto with an address on a connected socket
+            let sent = match read_socket {
+                true => socket.send(&out[..write]).await,
+                false => socket.send_to(&out[..write], peer).await,
+            };
+            if sent.is_err() {
+                break;
+            }
+        }
+
+        // Let any tasks waiting on stream data, flow control credit or closure re-check the connection
+        let (closed, timeout) = {
+            let mut state = state.lock().unwrap();
+            for waker in state.wakers.drain(..) {
+                waker.wake();
+            }
+
+            // Wake up for whichever comes first of quiche's timers and the abort timeout
+            let abort_timeout = state.abort_timeout.map(|t| t.checked_sub(state.last_received.elapsed()).unwrap_or_default());
+            let timeout = match (state.conn.timeout(), abort_timeout) {
+                (Some(a), Some(b)) => Some(std::cmp::min(a, b)),
+                (a, b) => a.or(b),
+            };
+
+            (state.conn.is_closed(), timeout)
+        };
+
+        if closed {
+            // Report closure which was not requested through Connection::close
+            let mut state = state.lock().unwrap();
+            if state.failed {
+                state.emit(|| ConnectionEvent::ConnectionError(TapsError::ConnectionAborted));
+            } else if !state.closed_locally {
+                state.emit(|| ConnectionEvent::Closed);
+            }
+            break;
+        }
+
+        let input = if abandoned {
+            match timeout {
+                Some(timeout) => {
+                    task::sleep(timeout).await;
+                    DriverInput::Timeout
+                },
+                None => break,
+            }
+        } else {
+            next_input(&socket, &mut events, read_socket, timeout).await
+        };
+
+        let mut state = state.lock().unwrap();
+        match input {
+            DriverInput::Event(DriverEvent::Datagram(mut datagram, from)) => {
+                // The peer's address has changed, for example after NAT rebinding
+                if from != peer {
+                    peer = from;
+                    state.emit(|| ConnectionEvent::PathChange(from));
+                }
+
+                // Process potentially coalesced packets.
+                state.conn.recv(&mut datagram).ok();
+                state.last_received = Instant::now();
+            },
+            DriverInput::SoftError => {
+                state.emit(|| ConnectionEvent::SoftError(TapsError::IcmpError));
+            },
+            DriverInput::Event(DriverEvent::Wake) => (),
+            DriverInput::Timeout => {
+                if let Some(abort_timeout) = state.abort_timeout {
+                    if state.last_received.elapsed() >= abort_timeout && state.conn.close(false, 0x0, b"timeout").is_ok() {
+                        state.failed = true;
+                    }
+                }
+                state.conn.on_timeout();
+            },
+            DriverInput::Abandoned => {
+                // Every handle to the connection has been dropped, so close it
+                abandoned = true;
+                state.conn.close(false, 0x0, b"").ok();
+            },
+        }
+    }
+}
+
+async fn next_input(
+    socket: &UdpSocket,
+    events: &mut UnboundedReceiver<DriverEvent>,
+    read_socket: bool,
+    timeout: Option<Duration>,
+) -> DriverInput {
+    let input = async {
+        if read_socket {
+            let recv = Box::pin(async {
+                let mut buf = vec![0; 65535];
+                let (len, from) = socket.recv_from(&mut buf).await?;
+                buf.truncate(len);
+                Ok::<(Vec<u8>, SocketAddr), std::io::Error>((buf, from))
+            });
+
+            match select(recv, events.next()).await {
+                Either::Left((Ok((datagram, from)), _)) => DriverInput::Event(DriverEvent::Datagram(datagram, from)),
+                // ICMP errors are reported on the connected socket as failed reads
+                Either::Left((Err(_), _)) => DriverInput::SoftError,
+                Either::Right((Some(event), _)) => DriverInput::Event(event),
+                Either::Right((None, _)) => DriverInput::Abandoned,
+            }
+        } else {
+            match events.next().await {
+                Some(event) => DriverInput::Event(event),
+                None => DriverInput::Abandoned,
+            }
+        }
+    };
+
+    return match timeout {
+        Some(timeout) => future::timeout(timeout, input).await.unwrap_or(DriverInput::Timeout),
+        None => input.await,
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn retry_token_test() {
+        let key = hmac::Key::generate(hmac::HMAC_SHA256, &SystemRandom::new()).unwrap();
+        let src: SocketAddr = "192.0.2.1:4433".parse().unwrap();
+        let odcid = [7; quiche::MAX_CONN_ID_LEN];
+
+        let token = mint_token(&key, &odcid, &src);
+        assert_eq!(validate_token(&key, &src, &token), Some(&odcid[..]));
+
+        // Not valid for another address, or once modified
+        let spoofed: SocketAddr = "192.0.2.2:4433".parse().unwrap();
+        assert_eq!(validate_token(&key, &spoofed, &token), None);
+
+        let mut forged = token.clone();
+        forged[9] ^= 1;
+        assert_eq!(validate_token(&key, &src, &forged), None);
+    }
+
+    #[test]
+    fn priority_urgency_test() {
+        assert_eq!(priority_urgency(0), 0);
+        assert_eq!(priority_urgency(31), 0);
+        assert_eq!(priority_urgency(32), 1);
+        assert_eq!(priority_urgency(100), 3);
+        assert_eq!(priority_urgency(224), MAX_URGENCY);
+        assert_eq!(priority_urgency(u32::MAX), MAX_URGENCY);
+    }
+}
@@ -0,0 +1,166 @@
+use crate::error::TapsError;
+
+use std::net::{IpAddr, SocketAddr};
+use std::ops::RangeInclusive;
+use std::str::FromStr;
+use std::time::Duration;
+
+// An address range in CIDR notation, e.g. 192.0.2.0/24 or 2001:db8::/32
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct IpNetwork {
+    pub address: IpAddr,
+    pub prefix_len: u8,
+}
+
+// Matches remote endpoints whose address is in the network and whose port is in the range, if given
+#[derive(Debug, Clone)]
+pub struct RemoteRule {
+    pub network: IpNetwork,
+    pub ports: Option<RangeInclusive<u16>>,
+}
+
+// Decides which remote endpoints a Listener accepts Connections from. Denied rules take precedence
+// over allowed rules. If there are no allowed rules or host names, any remote endpoint that is not
+// denied is accepted. Allowed host names are resolved when the Listener starts and again after every
+// resolve interval, so that changes to their addresses are picked up.
+#[derive(Debug, Clone)]
+pub struct RemoteFilter {
+    pub allowed: Vec<RemoteRule>,
+    pub denied: Vec<RemoteRule>,
+    pub allowed_host_names: Vec<(String, Option<RangeInclusive<u16>>)>,
+    pub resolve_interval: Duration,
+}
+
+impl IpNetwork {
+    pub fn new(address: IpAddr, prefix_len: u8) -> Result<IpNetwork, TapsError> {
+        if prefix_len > max_prefix_len(address) {
+            return Err(TapsError::InvalidAddressRange);
+        }
+
+        return Ok(IpNetwork {
+            address: canonical_ip(address),
+            prefix_len: prefix_len,
+        });
+    }
+
+    // A network containing the single address
+    pub fn host(address: IpAddr) -> IpNetwork {
+        let address = canonical_ip(address);
+        return IpNetwork {
+            address: address,
+            prefix_len: max_prefix_len(address),
+        };
+    }
+
+    pub fn contains(&self, address: IpAddr) -> bool {
+        match (self.address, canonical_ip(address)) {
+            (IpAddr::V4(network), IpAddr::V4(address)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix_len as u32).unwrap_or(0);
+                return u32::from(network) & mask == u32::from(address) & mask;
+            },
+            (IpAddr::V6(network), IpAddr::V6(address)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix_len as u32).unwrap_or(0);
+                return u128::from(network) & mask == u128::from(address) & mask;
+            },
+            _ => return false,
+        }
+    }
+}
+
+impl FromStr for IpNetwork {
+    type Err = TapsError;
+
+    // Accepts an address with an optional prefix length; a bare address is a single host
+    fn from_str(s: &str) -> Result<IpNetwork, TapsError> {
+        let mut parts = s.splitn(2, '/');
+        let address = match parts.next().unwrap().trim().parse::<IpAddr>() {
+            Ok(address) => address,
+            Err(_) => return Err(TapsError::InvalidAddressRange),
+        };
+
+        match parts.next() {
+            Some(prefix_len) => match prefix_len.trim().parse::<u8>() {
+                Ok(prefix_len) => return IpNetwork::new(address, prefix_len),
+                Err(_) => return Err(TapsError::InvalidAddressRange),
+            },
+            None => return Ok(IpNetwork::host(address)),
+        }
+    }
+}
+
+impl RemoteRule {
+    pub fn new(network: IpNetwork, ports: Option<RangeInclusive<u16>>) -> RemoteRule {
+        RemoteRule {
+            network: network,
+            ports: ports,
+        }
+    }
+
+    pub fn matches(&self, remote_addr: SocketAddr) -> bool {
+        let port_matches = match self.ports {
+            Some(ref ports) => ports.contains(&remote_addr.port()),
+            None => true,
+        };
+
+        return port_matches && self.network.contains(remote_addr.ip());
+    }
+}
+
+impl RemoteFilter {
+    pub fn new() -> RemoteFilter {
+        RemoteFilter {
+            allowed: vec![],
+            denied: vec![],
+            allowed_host_names: vec![],
+            resolve_interval: Duration::from_secs(60),
+        }
+    }
+
+    pub fn with_allowed(&mut self, network: IpNetwork, ports: Option<RangeInclusive<u16>>) -> () {
+        self.allowed.push(RemoteRule::new(network, ports));
+    }
+
+    pub fn with_denied(&mut self, network: IpNetwork, ports: Option<RangeInclusive<u16>>) -> () {
+        self.denied.push(RemoteRule::new(network, ports));
+    }
+
+    pub fn with_allowed_host_name(&mut self, host_name: &str, ports: Option<RangeInclusive<u16>>) -> () {
+        self.allowed_host_names.push((host_name.to_string(), ports));
+    }
+
+    pub fn with_resolve_interval(&mut self, resolve_interval: Duration) -> () {
+        self.resolve_interval = resolve_interval;
+    }
+
+    // Whether a Connection from the remote address is accepted, given the rules most recently
+    // resolved from the allowed host names
+    pub fn accepts(&self, remote_addr: SocketAddr, resolved: &[RemoteRule]) -> bool {
+        if self.denied.iter().any(|rule| rule.matches(remote_addr)) {
+            return false;
+        }
+
+        if self.allowed.is_empty() && self.allowed_host_names.is_empty() {
+            return true;
+        }
+
+        return self.allowed.iter().chain(resolved).any(|rule| rule.matches(remote_addr));
+    }
+}
+
+fn max_prefix_len(address: IpAddr) -> u8 {
+    match address {
+        IpAddr::V4(_) => return 32,
+        IpAddr::V6(_) => return 128,
+    }
+}
+
+// A dual-stack socket reports IPv4 peers as IPv4-mapped IPv6 addresses, match these as IPv4
+fn canonical_ip(address: IpAddr) -> IpAddr {
+    match address {
+        IpAddr::V6(v6) => match v6.to_ipv4_mapped() {
+            Some(v4) => return IpAddr::V4(v4),
+            None => return address,
+        },
+        IpAddr::V4(_) => return address,
+    }
+}
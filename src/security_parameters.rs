@@ -0,0 +1,31 @@
+#[derive(Debug, Clone)]
+pub struct SecurityParameters {
+    pub certificate_chain: Option<String>,
+    pub private_key: Option<String>,
+    pub trusted_certificates: Option<String>,
+}
+
+impl SecurityParameters {
+    pub fn new() -> SecurityParameters {
+        SecurityParameters {
+            certificate_chain: None,
+            private_key: None,
+            trusted_certificates: None,
+        }
+    }
+
+    // Path to a PEM file containing the leaf certificate, followed by any intermediate certificates
+    pub fn with_certificate_chain(&mut self, certificate_chain: &str) -> () {
+        self.certificate_chain = Some(certificate_chain.to_string());
+    }
+
+    // Path to a PEM file containing the private key for the leaf certificate
+    pub fn with_private_key(&mut self, private_key: &str) -> () {
+        self.private_key = Some(private_key.to_string());
+    }
+
+    // Path to a PEM file containing the certificate authorities trusted to verify a QUIC server
+    pub fn with_trusted_certificates(&mut self, trusted_certificates: &str) -> () {
+        self.trusted_certificates = Some(trusted_certificates.to_string());
+    }
+}
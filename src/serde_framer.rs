@@ -0,0 +1,182 @@
+use crate::error::TapsError;
+use crate::framer::{Framer, FramerInstance, FramerResult, LengthPrefixFramer, DelimiterFramer};
+use crate::message::Message;
+
+use std::marker::PhantomData;
+
+use serde::{de::DeserializeOwned, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WireFormat {
+    // One JSON value per line
+    JsonLines,
+    // CBOR values with a 32-bit length prefix
+    Cbor,
+    // bincode values with a 32-bit length prefix
+    Bincode,
+}
+
+// Sends and receives any type implementing serde's Serialize and Deserialize, in the chosen wire format
+#[derive(Debug, Clone, Copy)]
+pub struct SerdeFramer {
+    pub format: WireFormat,
+    pub max_message_size: usize,
+}
+
+struct SerdeFramerInstance<T, U> {
+    format: WireFormat,
+    // Delimits the serialized messages
+    byte_framer: Box<dyn FramerInstance<Vec<u8>, Vec<u8>> + Send + Sync>,
+    message_types: PhantomData<fn(T) -> U>,
+}
+
+impl SerdeFramer {
+    pub fn new(format: WireFormat) -> SerdeFramer {
+        SerdeFramer {
+            format: format,
+            max_message_size: 16 * 1024 * 1024,
+        }
+    }
+
+    pub fn with_max_message_size(&mut self, max_message_size: usize) -> () {
+        self.max_message_size = max_message_size;
+    }
+}
+
+impl<T: Serialize + 'static, U: DeserializeOwned + 'static> Framer<T, U> for SerdeFramer {
+    fn new_instance(&self) -> Box<dyn FramerInstance<T, U> + Send + Sync> {
+        let byte_framer = match self.format {
+            WireFormat::JsonLines => {
+                // A newline is always a valid delimiter
+                let mut delimiter_framer = DelimiterFramer::new(b"\n").unwrap();
+                delimiter_framer.with_max_line_length(self.max_message_size);
+                Framer::<Vec<u8>, Vec<u8>>::new_instance(&delimiter_framer)
+            },
+            WireFormat::Cbor | WireFormat::Bincode => {
+                let mut length_prefix_framer = LengthPrefixFramer::new();
+                length_prefix_framer.with_max_message_size(self.max_message_size);
+                Framer::<Vec<u8>, Vec<u8>>::new_instance(&length_prefix_framer)
+            },
+        };
+
+        return Box::new(SerdeFramerInstance::<T, U> {
+            format: self.format,
+            byte_framer: byte_framer,
+            message_types: PhantomData,
+        });
+    }
+}
+
+impl<T: Serialize, U: DeserializeOwned> FramerInstance<T, U> for SerdeFramerInstance<T, U> {
+    fn new_sent_message(&mut self, message: Message<T>) -> Result<Vec<u8>, TapsError> {
+        // Compact JSON never contains a newline, so each value fits on one line
+        let serialized = match self.format {
+            WireFormat::JsonLines => serde_json::to_vec(&message.data).map_err(|e| e.to_string()),
+            WireFormat::Cbor => serde_cbor::to_vec(&message.data).map_err(|e| e.to_string()),
+            WireFormat::Bincode => bincode::serialize(&message.data).map_err(|e| e.to_string()),
+        };
+
+        let serialized = match serialized {
+            Ok(serialized) => serialized,
+            Err(e) => return Err(TapsError::MessageFramingFailed(e)),
+        };
+
+        return self.byte_framer.new_sent_message(Message::new(serialized, message.message_context()));
+    }
+
+    fn handle_received_data(&mut self, received_data: &[u8]) -> Result<FramerResult<U>, TapsError> {
+        match self.byte_framer.handle_received_data(received_data)? {
+            FramerResult::Messages(messages) => {
+                let messages = messages.iter()
+                    .map(|message| self.deserialize(message))
+                    .collect::<Result<Vec<U>, TapsError>>()?;
+                return Ok(FramerResult::Messages(messages));
+            },
+            FramerResult::NeedMoreData => return Ok(FramerResult::NeedMoreData),
+        }
+    }
+
+    fn handle_end_of_data(&mut self) -> Result<Option<U>, TapsError> {
+        match self.byte_framer.handle_end_of_data()? {
+            Some(message) => return Ok(Some(self.deserialize(&message)?)),
+            None => return Ok(None),
+        }
+    }
+
+    fn take_partial_data(&mut self) -> Vec<u8> {
+        return self.byte_framer.take_partial_data();
+    }
+}
+
+impl<T, U: DeserializeOwned> SerdeFramerInstance<T, U> {
+    fn deserialize(&self, data: &[u8]) -> Result<U, TapsError> {
+        let deserialized = match self.format {
+            WireFormat::JsonLines => serde_json::from_slice(data).map_err(|e| e.to_string()),
+            WireFormat::Cbor => serde_cbor::from_slice(data).map_err(|e| e.to_string()),
+            WireFormat::Bincode => bincode::deserialize(data).map_err(|e| e.to_string()),
+        };
+
+        match deserialized {
+            Ok(message) => return Ok(message),
+            Err(e) => return Err(TapsError::MessageParseFailed(e)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type Value = (String, Vec<u32>, Option<bool>);
+
+    fn values() -> Vec<Value> {
+        return vec![
+            ("hello".to_string(), vec![1, 2, 3], Some(true)),
+            ("line\nbreak".to_string(), vec![], None),
+            (String::new(), vec![u32::MAX; 100], Some(false)),
+        ];
+    }
+
+    #[test]
+    fn serde_round_trip_test() {
+        for format in [WireFormat::JsonLines, WireFormat::Cbor, WireFormat::Bincode].iter() {
+            let framer = SerdeFramer::new(*format);
+
+            let mut sender = Framer::<Value, Value>::new_instance(&framer);
+            let mut data = vec![];
+            for value in values() {
+                data.extend(sender.new_sent_message(Message::new(value, None)).unwrap());
+            }
+
+            // Data split at every point is parsed once each value has been received in full
+            for split in 0..data.len() {
+                let mut receiver = Framer::<Value, Value>::new_instance(&framer);
+                let mut received = vec![];
+                for chunk in [&data[..split], &data[split..]].iter() {
+                    if let FramerResult::Messages(messages) = receiver.handle_received_data(chunk).unwrap() {
+                        received.extend(messages);
+                    }
+                }
+                assert_eq!(received, values(), "{:?} split at {}", format, split);
+                assert!(matches!(receiver.handle_end_of_data(), Ok(None)));
+            }
+        }
+    }
+
+    #[test]
+    fn serde_invalid_data_test() {
+        for format in [WireFormat::JsonLines, WireFormat::Cbor, WireFormat::Bincode].iter() {
+            let mut framer = SerdeFramer::new(*format);
+            framer.with_max_message_size(8);
+
+            // Values which do not deserialize as the message type are rejected
+            let mut instance = Framer::<Value, Value>::new_instance(&framer);
+            let data = Framer::<u32, u32>::new_instance(&framer).new_sent_message(Message::new(7, None)).unwrap();
+            assert!(matches!(instance.handle_received_data(&data), Err(TapsError::MessageParseFailed(_))), "{:?}", format);
+
+            // As are values larger than the maximum message size
+            let mut instance = Framer::<Value, Value>::new_instance(&framer);
+            assert!(matches!(instance.new_sent_message(Message::new(values().remove(0), None)), Err(TapsError::MessageFramingFailed(_))));
+        }
+    }
+}
@@ -0,0 +1,176 @@
+// Socket options which are not exposed by async-std, set directly on the underlying socket
+
+use std::io;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use socket2::{Domain, Protocol, Socket, Type};
+
+#[cfg(unix)]
+use std::os::unix::io::AsRawFd;
+
+#[cfg(unix)]
+pub(crate) trait AsSocket: AsRawFd {}
+#[cfg(unix)]
+impl<S: AsRawFd> AsSocket for S {}
+
+#[cfg(not(unix))]
+pub(crate) trait AsSocket {}
+#[cfg(not(unix))]
+impl<S> AsSocket for S {}
+
+#[cfg(unix)]
+fn set_option<S: AsSocket>(socket: &S, level: libc::c_int, name: libc::c_int, value: libc::c_int) -> io::Result<()> {
+    let result = unsafe {
+        libc::setsockopt(
+            socket.as_raw_fd(),
+            level,
+            name,
+            &value as *const libc::c_int as *const libc::c_void,
+            std::mem::size_of::<libc::c_int>() as libc::socklen_t,
+        )
+    };
+
+    if result == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    return Ok(());
+}
+
+// Mark packets sent on the socket with a DSCP codepoint
+#[cfg(unix)]
+pub(crate) fn set_dscp<S: AsSocket>(socket: &S, local_addr: SocketAddr, dscp: u8) -> io::Result<()> {
+    let tos = (dscp as libc::c_int) << 2;
+
+    match local_addr {
+        SocketAddr::V4(_) => return set_option(socket, libc::IPPROTO_IP, libc::IP_TOS, tos),
+        SocketAddr::V6(_) => return set_option(socket, libc::IPPROTO_IPV6, libc::IPV6_TCLASS, tos),
+    }
+}
+
+// Set or clear the Don't Fragment bit on packets sent on the socket
+#[cfg(target_os = "linux")]
+pub(crate) fn set_dont_fragment<S: AsSocket>(socket: &S, local_addr: SocketAddr, dont_fragment: bool) -> io::Result<()> {
+    match local_addr {
+        SocketAddr::V4(_) => {
+            let value = if dont_fragment { libc::IP_PMTUDISC_DO } else { libc::IP_PMTUDISC_WANT };
+            return set_option(socket, libc::IPPROTO_IP, libc::IP_MTU_DISCOVER, value);
+        },
+        SocketAddr::V6(_) => {
+            let value = if dont_fragment { libc::IPV6_PMTUDISC_DO } else { libc::IPV6_PMTUDISC_WANT };
+            return set_option(socket, libc::IPPROTO_IPV6, libc::IPV6_MTU_DISCOVER, value);
+        },
+    }
+}
+
+// Abort the connection if sent data remains unacknowledged for longer than the timeout
+#[cfg(target_os = "linux")]
+pub(crate) fn set_tcp_user_timeout<S: AsSocket>(socket: &S, timeout: Option<Duration>) -> io::Result<()> {
+    let millis = timeout.map(|t| t.as_millis() as libc::c_int).unwrap_or(0);
+    return set_option(socket, libc::IPPROTO_TCP, libc::TCP_USER_TIMEOUT, millis);
+}
+
+// Send keepalive probes once the connection has been idle for the timeout
+#[cfg(target_os = "linux")]
+pub(crate) fn set_tcp_keepalive<S: AsSocket>(socket: &S, timeout: Option<Duration>) -> io::Result<()> {
+    match timeout {
+        Some(timeout) => {
+            let secs = std::cmp::max(timeout.as_secs() as libc::c_int, 1);
+            set_option(socket, libc::IPPROTO_TCP, libc::TCP_KEEPIDLE, secs)?;
+            return set_option(socket, libc::SOL_SOCKET, libc::SO_KEEPALIVE, 1);
+        },
+        None => return set_option(socket, libc::SOL_SOCKET, libc::SO_KEEPALIVE, 0),
+    }
+}
+
+// Limit the rate at which packets are sent on the socket, in bits per second
+#[cfg(target_os = "linux")]
+pub(crate) fn set_max_pacing_rate<S: AsSocket>(socket: &S, rate: Option<u64>) -> io::Result<()> {
+    // SO_MAX_PACING_RATE is in bytes per second, with all bits set meaning unlimited
+    let bytes_per_sec = match rate {
+        Some(rate) => std::cmp::min(rate / 8, u32::MAX as u64 - 1) as u32,
+        None => u32::MAX,
+    };
+    return set_option(socket, libc::SOL_SOCKET, libc::SO_MAX_PACING_RATE, bytes_per_sec as libc::c_int);
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn set_tcp_user_timeout<S: AsSocket>(_socket: &S, timeout: Option<Duration>) -> io::Result<()> {
+    return unsupported(timeout.is_none());
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn set_tcp_keepalive<S: AsSocket>(_socket: &S, timeout: Option<Duration>) -> io::Result<()> {
+    return unsupported(timeout.is_none());
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn set_max_pacing_rate<S: AsSocket>(_socket: &S, rate: Option<u64>) -> io::Result<()> {
+    return unsupported(rate.is_none());
+}
+
+// Succeed only if the option is being set to its default, which needs no support from the platform
+#[cfg(not(target_os = "linux"))]
+fn unsupported(is_default: bool) -> io::Result<()> {
+    if is_default {
+        return Ok(());
+    }
+    return Err(io::Error::new(io::ErrorKind::Other, "Socket option is not supported on this platform"));
+}
+
+#[cfg(not(unix))]
+pub(crate) fn set_dscp<S: AsSocket>(_socket: &S, _local_addr: SocketAddr, dscp: u8) -> io::Result<()> {
+    return unsupported(dscp == 0);
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn set_dont_fragment<S: AsSocket>(_socket: &S, _local_addr: SocketAddr, dont_fragment: bool) -> io::Result<()> {
+    return unsupported(!dont_fragment);
+}
+
+// Allow other sockets which also set this option to bind the same local address and port, including
+// while one of them is listening
+#[cfg(unix)]
+pub(crate) fn set_reuse_port<S: AsSocket>(socket: &S) -> io::Result<()> {
+    return set_option(socket, libc::SOL_SOCKET, libc::SO_REUSEPORT, 1);
+}
+
+// SO_REUSEADDR already allows this on Windows
+#[cfg(not(unix))]
+pub(crate) fn set_reuse_port<S: AsSocket>(_socket: &S) -> io::Result<()> {
+    return Ok(());
+}
+
+// A non-blocking connect fails with EINPROGRESS on unix, or WSAEWOULDBLOCK on Windows, while the handshake completes
+pub(crate) fn connect_in_progress(error: &io::Error) -> bool {
+    #[cfg(unix)]
+    {
+        if error.raw_os_error() == Some(libc::EINPROGRESS) {
+            return true;
+        }
+    }
+    return error.kind() == io::ErrorKind::WouldBlock;
+}
+
+// Create a non-blocking socket for a listener. IPv6 sockets only receive IPv6 traffic, so that the
+// IPv4 and IPv6 wildcard addresses can be bound to the same port by separate listeners.
+pub(crate) fn listener_socket(local_addr: SocketAddr, socket_type: Type, protocol: Protocol) -> io::Result<Socket> {
+    let socket = Socket::new(Domain::for_address(local_addr), socket_type, Some(protocol))?;
+    if local_addr.is_ipv6() {
+        socket.set_only_v6(true)?;
+    }
+    socket.set_nonblocking(true)?;
+    return Ok(socket);
+}
+
+// Binding an IPv6 address fails with EAFNOSUPPORT on hosts without IPv6, or EADDRNOTAVAIL where no
+// interface has IPv6 enabled
+pub(crate) fn ipv6_unavailable(error: &io::Error) -> bool {
+    #[cfg(unix)]
+    {
+        if error.raw_os_error() == Some(libc::EAFNOSUPPORT) {
+            return true;
+        }
+    }
+    return error.kind() == io::ErrorKind::AddrNotAvailable;
+}
@@ -0,0 +1,323 @@
+use std::collections::HashMap;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use async_std::{
+    future,
+    net::UdpSocket,
+    task,
+    task::{Poll, Waker},
+};
+
+use futures::channel::mpsc::{unbounded, UnboundedReceiver, UnboundedSender};
+use futures::channel::oneshot;
+use futures::future::{poll_fn, select, Either};
+use futures::stream::StreamExt;
+use socket2::{Protocol, Type};
+
+use crate::socket_options;
+
+// A UDP flow with a single remote endpoint, used as the transport instance of a Connection.
+// Connections created by a UdpListener share the listener's socket, and are handed the
+// datagrams their remote endpoint sends to it.
+pub struct UdpConnection {
+    socket: Arc<UdpSocket>,
+    remote_addr: SocketAddr,
+    incoming: Option<UnboundedReceiver<Vec<u8>>>,
+    // Set for a rendezvous, whose probes may still arrive after the Connection has been made
+    rendezvous: bool,
+    // Data received from the peer during a rendezvous, returned by the first receive
+    first_datagram: Option<Vec<u8>>,
+}
+
+// Sent by both peers of a rendezvous until each knows that datagrams get through in both directions
+const RENDEZVOUS_PROBE: &[u8] = b"rs_taps rendezvous probe";
+const RENDEZVOUS_ACK: &[u8] = b"rs_taps rendezvous ack";
+const RENDEZVOUS_PROBE_INTERVAL: Duration = Duration::from_millis(100);
+
+// How many new connections a UDP or QUIC listener holds before they are accepted, as for a TCP
+// listen backlog. Once it is full, for example while a Listener's connection limit is reached,
+// datagrams from new remote endpoints are dropped.
+const ACCEPT_BACKLOG: usize = 128;
+
+// Counts the new connections created by a listener's background task which have not yet been accepted
+#[derive(Clone)]
+pub(crate) struct Backlog(Arc<Mutex<BacklogState>>);
+
+struct BacklogState {
+    len: usize,
+    // Set once the listener has been dropped, after which no more connections are accepted
+    closed: bool,
+    // Tasks waiting for room in the backlog
+    wakers: Vec<Waker>,
+}
+
+// Accepts UDP "connections" on a socket, demultiplexing datagrams by their source address.
+// The first datagram from a new remote endpoint yields a new connection.
+pub struct UdpListener {
+    incoming: UnboundedReceiver<(UdpConnection, SocketAddr)>,
+    backlog: Backlog,
+    // Dropped with the listener, telling the demultiplexing task to stop accepting new remote endpoints
+    _closed: oneshot::Sender<()>,
+}
+
+impl UdpConnection {
+    // Use a socket which has already been connected to the remote endpoint
+    pub(crate) fn connected(socket: UdpSocket, remote_addr: SocketAddr) -> UdpConnection {
+        return UdpConnection {
+            socket: Arc::new(socket),
+            remote_addr: remote_addr,
+            incoming: None,
+            rendezvous: false,
+            first_datagram: None,
+        };
+    }
+
+    // Punch a hole through NATs and firewalls to a peer performing a rendezvous, using a socket which
+    // has already been connected to it. Probes are sent until the peer acknowledges one, and the
+    // peer's probes are acknowledged, so that both peers finish once each has heard from the other.
+    pub(crate) async fn rendezvous(socket: UdpSocket, remote_addr: SocketAddr) -> io::Result<UdpConnection> {
+        let mut buf = [0; 65535];
+        let mut first_datagram = None;
+
+        loop {
+            socket.send(RENDEZVOUS_PROBE).await?;
+
+            // Errors such as ICMP port unreachable are expected until the peer's socket has been bound
+            let len = match future::timeout(RENDEZVOUS_PROBE_INTERVAL, socket.recv(&mut buf)).await {
+                Ok(Ok(len)) => len,
+                _ => continue,
+            };
+
+            if &buf[..len] == RENDEZVOUS_PROBE {
+                socket.send(RENDEZVOUS_ACK).await?;
+            } else if &buf[..len] == RENDEZVOUS_ACK {
+                // Acknowledge in turn, in case none of the peer's probes have arrived yet
+                socket.send(RENDEZVOUS_ACK).await?;
+                break;
+            } else {
+                // The peer has already finished, so it must have received an acknowledgement from us
+                first_datagram = Some(buf[..len].to_vec());
+                break;
+            }
+        }
+
+        return Ok(UdpConnection {
+            socket: Arc::new(socket),
+            remote_addr: remote_addr,
+            incoming: None,
+            rendezvous: true,
+            first_datagram: first_datagram,
+        });
+    }
+
+    pub fn remote_addr(&self) -> SocketAddr {
+        return self.remote_addr;
+    }
+
+    pub(crate) fn socket(&self) -> &UdpSocket {
+        return &self.socket;
+    }
+
+    // Whether the socket is shared with the other Connections of a UdpListener
+    pub(crate) fn is_demultiplexed(&self) -> bool {
+        return self.incoming.is_some();
+    }
+
+    pub async fn send(&self, buf: &[u8]) -> io::Result<usize> {
+        return match self.incoming {
+            Some(_) => self.socket.send_to(buf, self.remote_addr).await,
+            None => self.socket.send(buf).await,
+        };
+    }
+
+    pub async fn recv(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if let Some(datagram) = self.first_datagram.take() {
+            return Ok(copy_datagram(&datagram, buf));
+        }
+
+        let incoming = match self.incoming.as_mut() {
+            Some(incoming) => incoming,
+            None => return self.recv_connected(buf).await,
+        };
+
+        match incoming.next().await {
+            Some(datagram) => return Ok(copy_datagram(&datagram, buf)),
+            None => return Err(io::Error::new(io::ErrorKind::NotConnected, "UDP listener has stopped")),
+        }
+    }
+
+    async fn recv_connected(&self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let len = self.socket.recv(buf).await?;
+
+            // The peer keeps probing until one of its probes is acknowledged
+            if self.rendezvous && &buf[..len] == RENDEZVOUS_PROBE {
+                self.socket.send(RENDEZVOUS_ACK).await.ok();
+                continue;
+            }
+            if self.rendezvous && &buf[..len] == RENDEZVOUS_ACK {
+                continue;
+            }
+
+            return Ok(len);
+        }
+    }
+}
+
+// Excess data is discarded, as for a datagram read from a socket into a short buffer
+fn copy_datagram(datagram: &[u8], buf: &mut [u8]) -> usize {
+    let len = std::cmp::min(datagram.len(), buf.len());
+    buf[..len].copy_from_slice(&datagram[..len]);
+    return len;
+}
+
+impl UdpListener {
+    pub(crate) async fn bind(local_addr: SocketAddr) -> io::Result<UdpListener> {
+        let socket = bind_socket(local_addr)?;
+        let (sender, receiver) = unbounded();
+        let (closed_sender, closed) = oneshot::channel();
+        let backlog = Backlog::new();
+
+        task::spawn(demultiplex(Arc::new(socket), sender, backlog.clone(), closed));
+
+        return Ok(UdpListener {
+            incoming: receiver,
+            backlog: backlog,
+            _closed: closed_sender,
+        });
+    }
+
+    // Wait for a datagram from a new remote endpoint
+    pub async fn accept(&mut self) -> Option<(UdpConnection, SocketAddr)> {
+        let incoming_conn = self.incoming.next().await?;
+        self.backlog.release();
+        return Some(incoming_conn);
+    }
+}
+
+impl Backlog {
+    pub(crate) fn new() -> Backlog {
+        return Backlog(Arc::new(Mutex::new(BacklogState {
+            len: 0,
+            closed: false,
+            wakers: vec![],
+        })));
+    }
+
+    // Make room for a new connection, returning false if the backlog is full or has been closed
+    pub(crate) fn try_reserve(&self) -> bool {
+        let mut state = self.0.lock().unwrap();
+        if state.closed || state.len == ACCEPT_BACKLOG {
+            return false;
+        }
+        state.len += 1;
+        return true;
+    }
+
+    // Wait until there is room for a new connection, returning false if the backlog is closed first
+    pub(crate) async fn reserve(&self) -> bool {
+        return poll_fn(|cx| {
+            let mut state = self.0.lock().unwrap();
+            if state.closed {
+                return Poll::Ready(false);
+            }
+            if state.len < ACCEPT_BACKLOG {
+                state.len += 1;
+                return Poll::Ready(true);
+            }
+            state.wakers.push(cx.waker().clone());
+            return Poll::Pending;
+        }).await;
+    }
+
+    // Called once a connection has been accepted, or has failed before it could be
+    pub(crate) fn release(&self) {
+        let mut state = self.0.lock().unwrap();
+        state.len -= 1;
+        for waker in state.wakers.drain(..) {
+            waker.wake();
+        }
+    }
+
+    // Stop accepting connections, waking any tasks waiting for room
+    pub(crate) fn close(&self) {
+        let mut state = self.0.lock().unwrap();
+        state.closed = true;
+        for waker in state.wakers.drain(..) {
+            waker.wake();
+        }
+    }
+}
+
+// Bind a socket for a listener
+pub(crate) fn bind_socket(local_addr: SocketAddr) -> io::Result<UdpSocket> {
+    let socket = socket_options::listener_socket(local_addr, Type::DGRAM, Protocol::UDP)?;
+    socket.bind(&local_addr.into())?;
+
+    return Ok(UdpSocket::from(std::net::UdpSocket::from(socket)));
+}
+
+async fn demultiplex(
+    socket: Arc<UdpSocket>,
+    new_connections: UnboundedSender<(UdpConnection, SocketAddr)>,
+    backlog: Backlog,
+    closed: oneshot::Receiver<()>,
+) {
+    let mut buf = [0; 65535];
+    let mut peers = HashMap::<SocketAddr, UnboundedSender<Vec<u8>>>::new();
+    let mut listener_closed = Some(closed);
+
+    loop {
+        // Once the listener is closed, datagrams are still passed to the Connections it created,
+        // until they have all been closed too, but new remote endpoints are ignored
+        let received = match listener_closed.take() {
+            Some(closed) => match select(Box::pin(socket.recv_from(&mut buf)), closed).await {
+                Either::Left((received, closed)) => {
+                    listener_closed = Some(closed);
+                    received
+                },
+                Either::Right(_) => continue,
+            },
+            None => {
+                peers.retain(|_, peer| !peer.is_closed());
+                if peers.is_empty() {
+                    return;
+                }
+                socket.recv_from(&mut buf).await
+            },
+        };
+
+        let (len, src) = match received {
+            Ok(v) => v,
+            Err(_) => continue,
+        };
+
+        if let Some(peer) = peers.get(&src) {
+            if peer.unbounded_send(buf[..len].to_vec()).is_ok() {
+                continue;
+            }
+            // The Connection for this remote endpoint has been closed, treat it as a new remote endpoint
+            peers.remove(&src);
+        }
+
+        if listener_closed.is_none() || new_connections.is_closed() || !backlog.try_reserve() {
+            continue;
+        }
+
+        let (sender, receiver) = unbounded();
+        sender.unbounded_send(buf[..len].to_vec()).ok();
+        peers.insert(src, sender);
+
+        let connection = UdpConnection {
+            socket: socket.clone(),
+            remote_addr: src,
+            incoming: Some(receiver),
+            rendezvous: false,
+            first_datagram: None,
+        };
+        new_connections.unbounded_send((connection, src)).ok();
+    }
+}
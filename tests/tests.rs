@@ -5,10 +5,20 @@ use rs_taps::{
     selection_properties::{SelectionProperty, PreferenceLevel},
     preconnection::Preconnection,
     message::Message,
-    framer::HttpClientFramer,
+    message_context::MessageContext,
+    connection_event::ConnectionEvent,
+    connection_properties::{ConnectionProperty, ConnectionState},
+    framer::{DelimiterFramer, HttpClientFramer, HttpServerFramer},
+    candidate::{AddressFamily, CandidateSource},
+    listener::Listener,
+    listener_event::ListenerEvent,
+    remote_filter::RemoteFilter,
+    security_parameters::SecurityParameters,
 };
 
-use async_std::stream::StreamExt;
+use std::sync::Arc;
+
+use async_std::{io::WriteExt, stream::StreamExt, task};
 
 use http::{Request, Response};
 
@@ -26,7 +36,7 @@ async fn initiate_test() -> Result<(), TapsError> {
         None, 
         Some(remote), 
         Some(tp),
-        &HttpClientFramer{});
+        Arc::new(HttpClientFramer::new()));
 
     let connection = preconnection.initiate().await;
 
@@ -48,7 +58,7 @@ async fn listen_test() -> Result<(), TapsError> {
         Some(local),
         None, 
         Some(tp),
-        &HttpClientFramer{});
+        Arc::new(HttpClientFramer::new()));
 
     let mut listener = preconnection.listen().await?;
     listener.start().await?;
@@ -61,6 +71,334 @@ async fn listen_test() -> Result<(), TapsError> {
     Ok(())
 }
 
+#[async_std::test]
+async fn listener_remote_filter_test() -> Result<(), TapsError> {
+    let mut local = LocalEndpoint::new();
+    local.with_address("127.0.0.1");
+    local.with_port(12001);
+
+    let tp = TransportProperties::default();
+
+    let preconnection = Preconnection::<Request<()>, Response<()>>::new(
+        Some(local),
+        None,
+        Some(tp),
+        Arc::new(HttpClientFramer::new()));
+
+    let mut remote_filter = RemoteFilter::new();
+    remote_filter.with_denied("127.0.0.0/8".parse()?, None);
+
+    let mut listener = preconnection.listen().await?;
+    listener.with_remote_filter(remote_filter);
+    let mut events = listener.events();
+    listener.start().await?;
+
+    let _stream = async_std::net::TcpStream::connect("127.0.0.1:12001").await?;
+
+    match events.next().await {
+        Some(ListenerEvent::ConnectionRejected(remote_addr)) => assert!(remote_addr.ip().is_loopback()),
+        Some(event) => panic!("Unexpected Listener event: {:?}", event),
+        None => panic!("Listener events ended before the rejected Connection was reported"),
+    }
+
+    Ok(())
+}
+
+#[async_std::test]
+async fn listener_stop_test() -> Result<(), TapsError> {
+    let mut local = LocalEndpoint::new();
+    local.with_address("127.0.0.1");
+    local.with_port(12002);
+
+    let tp = TransportProperties::default();
+
+    let preconnection = Preconnection::<Request<()>, Response<()>>::new(
+        Some(local),
+        None,
+        Some(tp),
+        Arc::new(HttpClientFramer::new()));
+
+    let mut listener = preconnection.listen().await?;
+    let mut events = listener.events();
+    listener.set_new_connection_limit(0);
+    listener.start().await?;
+
+    // Held back by the connection limit, and closed when the Listener stops
+    let _stream = async_std::net::TcpStream::connect("127.0.0.1:12002").await?;
+
+    listener.stop().await;
+    assert!(listener.next().await.is_none());
+    assert!(matches!(events.next().await, Some(ListenerEvent::Stopped)));
+
+    Ok(())
+}
+
+#[async_std::test]
+async fn listener_local_endpoints_test() -> Result<(), TapsError> {
+    // Only a port - listens on the wildcard address of both IP families
+    let mut local = LocalEndpoint::new();
+    local.with_port(12003);
+
+    let mut other_local = LocalEndpoint::new();
+    other_local.with_address("localhost");
+    other_local.with_port(12004);
+
+    let mut tp = TransportProperties::default();
+    tp.require(SelectionProperty::Reliability);
+
+    let preconnection = Preconnection::<Request<()>, Response<()>>::new(
+        Some(local),
+        None,
+        Some(tp),
+        Arc::new(HttpClientFramer::new()));
+
+    let mut listener = preconnection.listen().await?;
+    listener.with_local_endpoint(other_local);
+    listener.start().await?;
+
+    let _stream = async_std::net::TcpStream::connect("127.0.0.1:12003").await?;
+    let _other_stream = async_std::net::TcpStream::connect("127.0.0.1:12004").await?;
+
+    assert!(listener.next().await.is_some());
+    assert!(listener.next().await.is_some());
+
+    Ok(())
+}
+
+#[async_std::test]
+async fn listener_without_local_endpoint_test() -> Result<(), TapsError> {
+    let mut tp = TransportProperties::default();
+    tp.require(SelectionProperty::Reliability);
+
+    // A Listener needs a local endpoint with a port before it starts
+    let mut listener = Preconnection::new_raw(None, None, Some(tp)).listen().await?;
+    assert!(matches!(listener.start().await, Err(TapsError::LocalEndpointNotProvided)));
+
+    listener.with_local_endpoint(LocalEndpoint::new());
+    assert!(matches!(listener.start().await, Err(TapsError::LocalEndpointPortNotProvided)));
+
+    // Which may be added to the Listener rather than the Preconnection
+    let mut listener = Preconnection::new_raw(None, None, Some(tp)).listen().await?;
+    let mut local = LocalEndpoint::new();
+    local.with_address("127.0.0.1");
+    local.with_port(12021);
+    listener.with_local_endpoint(local);
+    listener.start().await?;
+
+    let _stream = async_std::net::TcpStream::connect("127.0.0.1:12021").await?;
+    assert!(listener.next().await.is_some());
+
+    Ok(())
+}
+
+#[async_std::test]
+async fn listener_ipv6_bind_error_test() -> Result<(), TapsError> {
+    // Hold the IPv6 wildcard address only, on hosts which have IPv6
+    let socket = socket2::Socket::new(socket2::Domain::IPV6, socket2::Type::STREAM, None)?;
+    socket.set_only_v6(true)?;
+    let ipv6_wildcard_addr: std::net::SocketAddr = "[::]:12014".parse().unwrap();
+    if socket.bind(&ipv6_wildcard_addr.into()).is_err() {
+        return Ok(());
+    }
+    socket.listen(1)?;
+
+    let mut local = LocalEndpoint::new();
+    local.with_port(12014);
+
+    let mut tp = TransportProperties::default();
+    tp.require(SelectionProperty::Reliability);
+
+    // Only a missing IPv6 stack is tolerated, not an address which is already in use
+    let preconnection = Preconnection::new_raw(Some(local), None, Some(tp));
+    let mut listener = preconnection.listen().await?;
+    assert!(matches!(listener.start().await, Err(TapsError::Io(_))));
+
+    Ok(())
+}
+
+#[async_std::test]
+async fn listener_drop_test() -> Result<(), TapsError> {
+    let mut local = LocalEndpoint::new();
+    local.with_address("127.0.0.1");
+    local.with_port(12008);
+
+    let mut tp = TransportProperties::default();
+    tp.require(SelectionProperty::Reliability);
+
+    let preconnection = Preconnection::new_raw(Some(local), None, Some(tp));
+
+    let mut listener = preconnection.listen().await?;
+    listener.set_new_connection_limit(0);
+    listener.start().await?;
+
+    // Dropping the Listener without stopping it still closes its socket
+    drop(listener);
+    task::sleep(std::time::Duration::from_millis(100)).await;
+    std::net::TcpListener::bind("127.0.0.1:12008")?;
+
+    Ok(())
+}
+
+#[async_std::test]
+async fn tcp_local_endpoint_test() -> Result<(), TapsError> {
+    let peer_listener = async_std::net::TcpListener::bind("127.0.0.1:12010").await?;
+
+    let mut local = LocalEndpoint::new();
+    local.with_address("127.0.0.1");
+    local.with_port(12011);
+
+    let mut remote = RemoteEndpoint::new();
+    remote.with_address("127.0.0.1");
+    remote.with_port(12010);
+
+    let mut tp = TransportProperties::default();
+    tp.require(SelectionProperty::Reliability);
+
+    let preconnection = Preconnection::new_raw(Some(local), Some(remote), Some(tp));
+    let _connection = preconnection.initiate().await?;
+
+    // The connection is made from the local endpoint
+    let (_stream, peer_addr) = peer_listener.accept().await?;
+    assert_eq!(peer_addr, "127.0.0.1:12011".parse().unwrap());
+
+    Ok(())
+}
+
+#[async_std::test]
+async fn received_partial_test() -> Result<(), TapsError> {
+    let peer_listener = async_std::net::TcpListener::bind("127.0.0.1:12017").await?;
+
+    let mut remote = RemoteEndpoint::new();
+    remote.with_address("127.0.0.1");
+    remote.with_port(12017);
+
+    let mut tp = TransportProperties::default();
+    tp.require(SelectionProperty::Reliability);
+
+    let preconnection = Preconnection::new_raw(None, Some(remote), Some(tp));
+    let mut connection = preconnection.initiate().await?;
+    let mut events = connection.events();
+
+    let (mut stream, _) = peer_listener.accept().await?;
+    stream.write_all(b"hello").await?;
+
+    // Without a framer, data received on TCP is only part of a message
+    let message = connection.receive().await?;
+    assert_eq!(message.data, b"hello");
+
+    assert!(matches!(events.next().await, Some(ConnectionEvent::Ready)));
+    match events.next().await {
+        Some(ConnectionEvent::ReceivedPartial(partial, _, end_of_message)) => {
+            assert_eq!(partial.data, b"hello");
+            assert!(!end_of_message);
+        },
+        event => panic!("Unexpected event {:?}", event),
+    }
+
+    Ok(())
+}
+
+#[async_std::test]
+async fn received_partial_too_large_test() -> Result<(), TapsError> {
+    let peer_listener = async_std::net::TcpListener::bind("127.0.0.1:12018").await?;
+
+    let mut remote = RemoteEndpoint::new();
+    remote.with_address("127.0.0.1");
+    remote.with_port(12018);
+
+    let mut tp = TransportProperties::default();
+    tp.require(SelectionProperty::Reliability);
+
+    let mut framer = DelimiterFramer::new(b"\n")?;
+    framer.with_max_line_length(4);
+
+    let preconnection = Preconnection::<Vec<u8>, Vec<u8>>::new(None, Some(remote), Some(tp), Arc::new(framer));
+    let mut connection = preconnection.initiate().await?;
+    let mut events = connection.events();
+
+    let (mut stream, _) = peer_listener.accept().await?;
+    stream.write_all(b"too long\n").await?;
+
+    // What was received of a message larger than the maximum size is delivered before the error
+    assert!(matches!(connection.receive().await, Err(TapsError::MessageTooLarge)));
+
+    assert!(matches!(events.next().await, Some(ConnectionEvent::Ready)));
+    match events.next().await {
+        Some(ConnectionEvent::ReceivedPartial(partial, _, end_of_message)) => {
+            assert_eq!(partial.data, b"too long\n");
+            assert!(!end_of_message);
+        },
+        event => panic!("Unexpected event {:?}", event),
+    }
+    assert!(matches!(events.next().await, Some(ConnectionEvent::ReceiveError(_, _))));
+
+    Ok(())
+}
+
+#[async_std::test]
+async fn udp_without_local_endpoint_test() -> Result<(), TapsError> {
+    let peer = async_std::net::UdpSocket::bind("127.0.0.1:12012").await?;
+
+    let mut remote = RemoteEndpoint::new();
+    remote.with_address("127.0.0.1");
+    remote.with_port(12012);
+
+    let mut tp = TransportProperties::default();
+    tp.add(SelectionProperty::Reliability, PreferenceLevel::Prohibit);
+    tp.add(SelectionProperty::PreserveOrder, PreferenceLevel::Ignore);
+    tp.add(SelectionProperty::CongestionControl, PreferenceLevel::Ignore);
+
+    // Without a local endpoint, the Connection uses an ephemeral port
+    let preconnection = Preconnection::new_raw(None, Some(remote), Some(tp));
+    let mut connection = preconnection.initiate().await?;
+    connection.send(Message::new(b"ping".to_vec(), None)).await?;
+
+    let mut buf = [0; 16];
+    let (len, from) = peer.recv_from(&mut buf).await?;
+    assert_eq!(&buf[..len], b"ping");
+    assert_ne!(from.port(), 0);
+
+    peer.send_to(b"pong", from).await?;
+    assert_eq!(connection.receive().await?.data, b"pong".to_vec());
+
+    Ok(())
+}
+
+#[async_std::test]
+async fn udp_listener_backlog_test() -> Result<(), TapsError> {
+    let mut local = LocalEndpoint::new();
+    local.with_address("127.0.0.1");
+    local.with_port(12013);
+
+    let mut tp = TransportProperties::default();
+    tp.add(SelectionProperty::Reliability, PreferenceLevel::Prohibit);
+    tp.add(SelectionProperty::PreserveOrder, PreferenceLevel::Ignore);
+    tp.add(SelectionProperty::CongestionControl, PreferenceLevel::Ignore);
+
+    let preconnection = Preconnection::new_raw(Some(local), None, Some(tp));
+    let mut listener = preconnection.listen().await?;
+    listener.set_new_connection_limit(0);
+    listener.start().await?;
+
+    // While the connection limit is reached, remote endpoints beyond the backlog are dropped
+    let mut peers = vec![];
+    for _ in 0..200 {
+        let peer = async_std::net::UdpSocket::bind("127.0.0.1:0").await?;
+        peer.send_to(b"hello", "127.0.0.1:12013").await?;
+        peers.push(peer);
+    }
+    task::sleep(std::time::Duration::from_millis(200)).await;
+
+    listener.set_new_connection_limit(usize::MAX);
+    let mut accepted = 0;
+    while let Ok(Some(_)) = async_std::future::timeout(std::time::Duration::from_millis(200), listener.next()).await {
+        accepted += 1;
+    }
+    assert_eq!(accepted, 128);
+
+    Ok(())
+}
+
 #[async_std::test]
 async fn send_receive_test() -> Result<(), TapsError> {
     let mut remote = RemoteEndpoint::new();
@@ -72,7 +410,7 @@ async fn send_receive_test() -> Result<(), TapsError> {
         None, 
         Some(remote), 
         Some(tp), 
-        &HttpClientFramer{});
+        Arc::new(HttpClientFramer::new()));
 
     let mut connection = preconnection.initiate().await?;
 
@@ -90,4 +428,757 @@ async fn send_receive_test() -> Result<(), TapsError> {
     println!("Received Message: {:?}", &received_message);
 
     Ok(())
-}
\ No newline at end of file
+}
+
+#[async_std::test]
+async fn close_test() -> Result<(), TapsError> {
+    let mut remote = RemoteEndpoint::new();
+    remote.with_host_name("gla.ac.uk");
+    remote.with_port(80);
+    let tp = TransportProperties::default();
+
+    let preconnection = Preconnection::<Request<()>, Response<()>>::new(
+        None, 
+        Some(remote), 
+        Some(tp), 
+        Arc::new(HttpClientFramer::new()));
+
+    let mut connection = preconnection.initiate().await?;
+    connection.close().await?;
+
+    Ok(())
+}
+
+#[async_std::test]
+async fn tcp_close_test() -> Result<(), TapsError> {
+    let peer_listener = async_std::net::TcpListener::bind("127.0.0.1:12019").await?;
+
+    let mut remote = RemoteEndpoint::new();
+    remote.with_address("127.0.0.1");
+    remote.with_port(12019);
+
+    let mut tp = TransportProperties::default();
+    tp.require(SelectionProperty::Reliability);
+
+    let preconnection = Preconnection::new_raw(None, Some(remote), Some(tp));
+    let mut connection = preconnection.initiate().await?;
+    let (mut stream, _) = peer_listener.accept().await?;
+
+    // The peer never closes its side, so the connection is dropped once the close times out
+    let closed = async_std::future::timeout(std::time::Duration::from_secs(10), connection.close()).await;
+    assert!(matches!(closed, Ok(Ok(()))));
+    assert_eq!(connection.get_properties().state, ConnectionState::Closed);
+
+    let mut buf = [0; 16];
+    assert_eq!(async_std::io::ReadExt::read(&mut stream, &mut buf).await?, 0);
+
+    Ok(())
+}
+
+#[async_std::test]
+async fn quic_close_test() -> Result<(), TapsError> {
+    let mut listener = quic_listener(12020).await?;
+    listener.start().await?;
+
+    let mut connection = quic_preconnection(12020).initiate().await?;
+    connection.send(Message::new(b"hello".to_vec(), None)).await?;
+
+    let mut accepted = listener.next().await.unwrap();
+    assert_eq!(accepted.receive().await?.data, b"hello".to_vec());
+    let mut events = accepted.events();
+
+    // The peer is sent CONNECTION_CLOSE, so both ends close well before the idle timeout
+    let closed = async_std::future::timeout(std::time::Duration::from_secs(5), async {
+        connection.close().await?;
+        while let Some(event) = events.next().await {
+            if let ConnectionEvent::Closed = event {
+                return Ok(true);
+            }
+        }
+        return Ok::<bool, TapsError>(false);
+    }).await;
+    assert!(matches!(closed, Ok(Ok(true))));
+
+    Ok(())
+}
+
+#[async_std::test]
+async fn events_test() -> Result<(), TapsError> {
+    let mut remote = RemoteEndpoint::new();
+    remote.with_host_name("gla.ac.uk");
+    remote.with_port(80);
+    let tp = TransportProperties::default();
+
+    let preconnection = Preconnection::<Request<()>, Response<()>>::new(
+        None, 
+        Some(remote), 
+        Some(tp), 
+        Arc::new(HttpClientFramer::new()));
+
+    let mut connection = preconnection.initiate().await?;
+    let mut events = connection.events();
+
+    let request = Request::builder()
+        .method("GET")
+        .uri("www.gla.ac.uk")
+        .body(())
+        .unwrap();
+
+    connection.send(Message::<Request<()>>::new(request, None)).await?;
+    connection.close().await?;
+
+    assert!(matches!(events.next().await, Some(ConnectionEvent::Ready)));
+    assert!(matches!(events.next().await, Some(ConnectionEvent::Sent(_))));
+    assert!(matches!(events.next().await, Some(ConnectionEvent::Closed)));
+
+    Ok(())
+}
+
+#[async_std::test]
+async fn unsupported_message_property_test() -> Result<(), TapsError> {
+    let mut remote = RemoteEndpoint::new();
+    remote.with_host_name("gla.ac.uk");
+    remote.with_port(80);
+    let tp = TransportProperties::default();
+
+    let preconnection = Preconnection::<Request<()>, Response<()>>::new(
+        None, 
+        Some(remote), 
+        Some(tp), 
+        Arc::new(HttpClientFramer::new()));
+
+    let mut connection = preconnection.initiate().await?;
+
+    let request = Request::builder()
+        .method("GET")
+        .uri("www.gla.ac.uk")
+        .body(())
+        .unwrap();
+
+    // Partial checksum coverage is not provided by any reliable protocol stack
+    let mut message_context = MessageContext::new();
+    message_context.with_checksum_len(8);
+
+    let result = connection.send(Message::<Request<()>>::new(request, Some(message_context))).await;
+    assert!(matches!(result, Err(TapsError::MessagePropertyNotSupported)));
+
+    Ok(())
+}
+
+#[async_std::test]
+async fn connection_properties_test() -> Result<(), TapsError> {
+    let mut remote = RemoteEndpoint::new();
+    remote.with_host_name("gla.ac.uk");
+    remote.with_port(80);
+    let tp = TransportProperties::default();
+
+    let preconnection = Preconnection::<Request<()>, Response<()>>::new(
+        None, 
+        Some(remote), 
+        Some(tp), 
+        Arc::new(HttpClientFramer::new()));
+
+    let mut connection = preconnection.initiate().await?;
+    assert_eq!(connection.get_properties().state, ConnectionState::Established);
+
+    connection.set_property(ConnectionProperty::ConnectionPriority(5))?;
+    assert_eq!(connection.get_properties().priority, 5);
+
+    // No protocol stack can guarantee a minimum sending rate
+    let result = connection.set_property(ConnectionProperty::MinSendRate(Some(1_000_000)));
+    assert!(matches!(result, Err(TapsError::ConnectionPropertyNotSupported)));
+
+    connection.close().await?;
+    assert_eq!(connection.get_properties().state, ConnectionState::Closed);
+
+    Ok(())
+}
+
+#[async_std::test]
+async fn connection_properties_loopback_test() -> Result<(), TapsError> {
+    let peer_listener = async_std::net::TcpListener::bind("127.0.0.1:12022").await?;
+
+    let mut remote = RemoteEndpoint::new();
+    remote.with_address("127.0.0.1");
+    remote.with_port(12022);
+
+    let mut tp = TransportProperties::default();
+    tp.require(SelectionProperty::Reliability);
+
+    let preconnection = Preconnection::new_raw(None, Some(remote), Some(tp));
+    let mut connection = preconnection.initiate().await?;
+    assert_eq!(connection.get_properties().state, ConnectionState::Established);
+
+    connection.set_property(ConnectionProperty::ConnectionPriority(5))?;
+    assert_eq!(connection.get_properties().priority, 5);
+
+    // No protocol stack can guarantee a minimum sending rate
+    let result = connection.set_property(ConnectionProperty::MinSendRate(Some(1_000_000)));
+    assert!(matches!(result, Err(TapsError::ConnectionPropertyNotSupported)));
+
+    // The peer closes its side once accepted, so the close completes straight away
+    let (stream, _) = peer_listener.accept().await?;
+    drop(stream);
+
+    connection.close().await?;
+    assert_eq!(connection.get_properties().state, ConnectionState::Closed);
+
+    Ok(())
+}
+
+#[async_std::test]
+async fn clone_connection_test() -> Result<(), TapsError> {
+    let mut remote = RemoteEndpoint::new();
+    remote.with_host_name("gla.ac.uk");
+    remote.with_port(80);
+    let tp = TransportProperties::default();
+
+    let preconnection = Preconnection::<Request<()>, Response<()>>::new(
+        None, 
+        Some(remote), 
+        Some(tp), 
+        Arc::new(HttpClientFramer::new()));
+
+    let mut connection = preconnection.initiate().await?;
+    connection.set_property(ConnectionProperty::ConnectionPriority(5))?;
+
+    // Connections in a group share their properties
+    let mut cloned_connection = connection.clone_connection().await?;
+    assert_eq!(cloned_connection.get_properties().priority, 5);
+
+    let request = Request::builder()
+        .method("GET")
+        .uri("www.gla.ac.uk")
+        .body(())
+        .unwrap();
+
+    cloned_connection.send(Message::<Request<()>>::new(request, None)).await?;
+
+    cloned_connection.close().await?;
+    connection.close().await?;
+
+    Ok(())
+}
+
+#[async_std::test]
+async fn tcp_clone_connection_test() -> Result<(), TapsError> {
+    let peer_listener = async_std::net::TcpListener::bind("127.0.0.1:12023").await?;
+
+    let mut remote = RemoteEndpoint::new();
+    remote.with_address("127.0.0.1");
+    remote.with_port(12023);
+
+    let mut tp = TransportProperties::default();
+    tp.require(SelectionProperty::Reliability);
+
+    let preconnection = Preconnection::new_raw(None, Some(remote), Some(tp));
+    let mut connection = preconnection.initiate().await?;
+    connection.set_property(ConnectionProperty::ConnectionPriority(5))?;
+    let (_stream, _) = peer_listener.accept().await?;
+
+    // Without multistreaming, a cloned Connection is a new TCP connection sharing the group's properties
+    let mut cloned_connection = connection.clone_connection().await?;
+    assert_eq!(cloned_connection.get_properties().priority, 5);
+    let (mut cloned_stream, _) = peer_listener.accept().await?;
+
+    cloned_connection.send(Message::new(b"hello".to_vec(), None)).await?;
+
+    let mut buf = [0; 16];
+    let len = async_std::io::ReadExt::read(&mut cloned_stream, &mut buf).await?;
+    assert_eq!(&buf[..len], b"hello");
+
+    Ok(())
+}
+
+#[async_std::test]
+async fn quic_clone_connection_test() -> Result<(), TapsError> {
+    let mut local = LocalEndpoint::new();
+    local.with_address("127.0.0.1");
+    local.with_port(12009);
+
+    let mut remote = RemoteEndpoint::new();
+    remote.with_address("127.0.0.1");
+    remote.with_port(12009);
+
+    // Only QUIC provides multistreaming
+    let mut tp = TransportProperties::default();
+    tp.require(SelectionProperty::Multistreaming);
+
+    let mut security_parameters = SecurityParameters::new();
+    security_parameters.with_certificate_chain(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/cert.crt"));
+    security_parameters.with_private_key(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/cert.key"));
+
+    let mut listener_preconnection = Preconnection::new_raw(Some(local), None, Some(tp.clone()));
+    listener_preconnection.with_security_parameters(security_parameters);
+    let mut listener = listener_preconnection.listen().await?;
+    listener.start().await?;
+
+    // The test certificate is issued by a test certificate authority
+    let mut client_security_parameters = SecurityParameters::new();
+    client_security_parameters.with_trusted_certificates(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/rootca.crt"));
+
+    let mut preconnection = Preconnection::new_raw(None, Some(remote), Some(tp));
+    preconnection.with_security_parameters(client_security_parameters);
+    let mut connection = preconnection.initiate().await?;
+    connection.send(Message::new(b"first".to_vec(), None)).await?;
+
+    let mut accepted = listener.next().await.unwrap();
+    assert_eq!(accepted.receive().await?.data, b"first".to_vec());
+
+    // A cloned Connection opens a new stream, which the listener accepts as another Connection
+    let mut cloned_connection = connection.clone_connection().await?;
+    cloned_connection.send(Message::new(b"second".to_vec(), None)).await?;
+
+    let mut accepted_clone = listener.next().await.unwrap();
+    assert_eq!(accepted_clone.receive().await?.data, b"second".to_vec());
+
+    // Each stream carries only the messages of its own Connection
+    accepted_clone.send(Message::new(b"reply".to_vec(), None)).await?;
+    assert_eq!(cloned_connection.receive().await?.data, b"reply".to_vec());
+
+    accepted.send(Message::new(b"first reply".to_vec(), None)).await?;
+    assert_eq!(connection.receive().await?.data, b"first reply".to_vec());
+
+    Ok(())
+}
+
+// A QUIC Listener on a loopback port, using the test certificate. It is started by the caller.
+async fn quic_listener(port: u16) -> Result<Listener<Vec<u8>, Vec<u8>>, TapsError> {
+    let mut local = LocalEndpoint::new();
+    local.with_address("127.0.0.1");
+    local.with_port(port);
+
+    // Only QUIC provides multistreaming
+    let mut tp = TransportProperties::default();
+    tp.require(SelectionProperty::Multistreaming);
+
+    let mut security_parameters = SecurityParameters::new();
+    security_parameters.with_certificate_chain(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/cert.crt"));
+    security_parameters.with_private_key(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/cert.key"));
+
+    let mut preconnection = Preconnection::new_raw(Some(local), None, Some(tp));
+    preconnection.with_security_parameters(security_parameters);
+    return preconnection.listen().await;
+}
+
+// A QUIC Preconnection to a loopback port, trusting the test certificate authority
+fn quic_preconnection(port: u16) -> Preconnection<Vec<u8>, Vec<u8>> {
+    let mut remote = RemoteEndpoint::new();
+    remote.with_address("127.0.0.1");
+    remote.with_port(port);
+
+    let mut tp = TransportProperties::default();
+    tp.require(SelectionProperty::Multistreaming);
+
+    let mut security_parameters = SecurityParameters::new();
+    security_parameters.with_trusted_certificates(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/rootca.crt"));
+
+    let mut preconnection = Preconnection::new_raw(None, Some(remote), Some(tp));
+    preconnection.with_security_parameters(security_parameters);
+    return preconnection;
+}
+
+#[async_std::test]
+async fn quic_listener_remote_filter_test() -> Result<(), TapsError> {
+    let mut remote_filter = RemoteFilter::new();
+    remote_filter.with_denied("127.0.0.0/8".parse()?, None);
+
+    let mut listener = quic_listener(12015).await?;
+    listener.with_remote_filter(remote_filter);
+    let mut events = listener.events();
+    listener.start().await?;
+
+    let mut connection = quic_preconnection(12015).initiate().await?;
+
+    match events.next().await {
+        Some(ListenerEvent::ConnectionRejected(remote_addr)) => assert!(remote_addr.ip().is_loopback()),
+        Some(event) => panic!("Unexpected Listener event: {:?}", event),
+        None => panic!("Listener events ended before the rejected Connection was reported"),
+    }
+
+    // The refused connection is closed by the listener, well before the idle timeout
+    let received = async_std::future::timeout(std::time::Duration::from_secs(5), connection.receive()).await;
+    assert!(matches!(received, Ok(Err(_))));
+
+    Ok(())
+}
+
+#[async_std::test]
+async fn quic_listener_stop_test() -> Result<(), TapsError> {
+    let mut listener = quic_listener(12016).await?;
+    listener.set_new_connection_limit(0);
+    listener.start().await?;
+
+    // Held back by the connection limit, and closed when the Listener stops
+    let mut connection = quic_preconnection(12016).initiate().await?;
+    let mut events = connection.events();
+    assert!(matches!(events.next().await, Some(ConnectionEvent::Ready)));
+
+    listener.stop().await;
+    assert!(listener.next().await.is_none());
+
+    let event = async_std::future::timeout(std::time::Duration::from_secs(5), events.next()).await;
+    assert!(matches!(event, Ok(Some(ConnectionEvent::Closed))));
+
+    Ok(())
+}
+
+#[async_std::test]
+async fn http_pipelining_test() -> Result<(), TapsError> {
+    let mut remote = RemoteEndpoint::new();
+    remote.with_host_name("gla.ac.uk");
+    remote.with_port(80);
+    let tp = TransportProperties::default();
+
+    let preconnection = Preconnection::<Request<Vec<u8>>, Response<Vec<u8>>>::new(
+        None, 
+        Some(remote), 
+        Some(tp), 
+        Arc::new(HttpClientFramer::new()));
+
+    let mut connection = preconnection.initiate().await?;
+
+    // Both requests are sent before either response is received
+    for path in &["/", "/robots.txt"] {
+        let request = Request::builder()
+            .method("GET")
+            .uri(format!("http://www.gla.ac.uk{}", path))
+            .body(Vec::new())
+            .unwrap();
+
+        connection.send(Message::<Request<Vec<u8>>>::new(request, None)).await?;
+    }
+
+    for _ in 0..2 {
+        let received_message = connection.receive().await?;
+        assert!(received_message.data.status().as_u16() >= 200);
+    }
+
+    connection.close().await?;
+
+    Ok(())
+}
+
+#[async_std::test]
+async fn http_pipelining_loopback_test() -> Result<(), TapsError> {
+    let mut local = LocalEndpoint::new();
+    local.with_address("127.0.0.1");
+    local.with_port(12024);
+
+    let mut remote = RemoteEndpoint::new();
+    remote.with_address("127.0.0.1");
+    remote.with_port(12024);
+
+    let mut tp = TransportProperties::default();
+    tp.require(SelectionProperty::Reliability);
+
+    let listener_preconnection = Preconnection::<Response<Vec<u8>>, Request<Vec<u8>>>::new(
+        Some(local),
+        None,
+        Some(tp),
+        Arc::new(HttpServerFramer::new()));
+    let mut listener = listener_preconnection.listen().await?;
+    listener.start().await?;
+
+    let preconnection = Preconnection::<Request<Vec<u8>>, Response<Vec<u8>>>::new(
+        None,
+        Some(remote),
+        Some(tp),
+        Arc::new(HttpClientFramer::new()));
+    let mut connection = preconnection.initiate().await?;
+
+    // Both requests are sent before either response is received
+    for path in &["/", "/robots.txt"] {
+        let request = Request::builder()
+            .method("GET")
+            .uri(format!("http://127.0.0.1:12024{}", path))
+            .body(Vec::new())
+            .unwrap();
+
+        connection.send(Message::<Request<Vec<u8>>>::new(request, None)).await?;
+    }
+
+    // Responses are sent in the order the requests were received
+    let mut accepted = listener.next().await.unwrap();
+    for _ in 0..2 {
+        let request = accepted.receive().await?.data;
+        let response = Response::builder()
+            .status(200)
+            .body(request.uri().path().as_bytes().to_vec())
+            .unwrap();
+
+        accepted.send(Message::new(response, None)).await?;
+    }
+
+    for path in &["/", "/robots.txt"] {
+        let received_message = connection.receive().await?;
+        assert_eq!(received_message.data.status().as_u16(), 200);
+        assert_eq!(received_message.data.body(), path.as_bytes());
+    }
+
+    Ok(())
+}
+
+#[async_std::test]
+async fn raw_bytes_test() -> Result<(), TapsError> {
+    let mut remote = RemoteEndpoint::new();
+    remote.with_host_name("gla.ac.uk");
+    remote.with_port(80);
+    let tp = TransportProperties::default();
+
+    let preconnection = Preconnection::new_raw(None, Some(remote), Some(tp));
+
+    let mut connection = preconnection.initiate().await?;
+
+    let request = b"GET / HTTP/1.1\r\nHost: www.gla.ac.uk\r\n\r\n".to_vec();
+    connection.send(Message::new(request, None)).await?;
+
+    let received_message = connection.receive().await?;
+    assert!(received_message.data.starts_with(b"HTTP/"));
+
+    connection.close().await?;
+
+    Ok(())
+}
+
+#[async_std::test]
+async fn raw_bytes_loopback_test() -> Result<(), TapsError> {
+    let peer_listener = async_std::net::TcpListener::bind("127.0.0.1:12025").await?;
+
+    let mut remote = RemoteEndpoint::new();
+    remote.with_address("127.0.0.1");
+    remote.with_port(12025);
+
+    let mut tp = TransportProperties::default();
+    tp.require(SelectionProperty::Reliability);
+
+    let preconnection = Preconnection::new_raw(None, Some(remote), Some(tp));
+    let mut connection = preconnection.initiate().await?;
+    let (mut stream, _) = peer_listener.accept().await?;
+
+    // Bytes are sent and received without any framing
+    connection.send(Message::new(vec![0, 1, 2, 255], None)).await?;
+
+    let mut buf = [0; 16];
+    let len = async_std::io::ReadExt::read(&mut stream, &mut buf).await?;
+    assert_eq!(&buf[..len], &[0, 1, 2, 255]);
+
+    stream.write_all(&[255, 0]).await?;
+    assert_eq!(connection.receive().await?.data, vec![255, 0]);
+
+    Ok(())
+}
+
+#[async_std::test]
+async fn spawned_connection_test() -> Result<(), TapsError> {
+    let mut remote = RemoteEndpoint::new();
+    remote.with_host_name("gla.ac.uk");
+    remote.with_port(80);
+    let tp = TransportProperties::default();
+
+    let preconnection = Preconnection::<Request<()>, Response<()>>::new(
+        None, 
+        Some(remote), 
+        Some(tp), 
+        Arc::new(HttpClientFramer::new()));
+
+    let mut connection = preconnection.initiate().await?;
+
+    // Connections own their endpoints and framer, so can be moved into another task
+    let received_message = task::spawn(async move {
+        let request = Request::builder()
+            .method("GET")
+            .uri("www.gla.ac.uk")
+            .body(())
+            .unwrap();
+
+        connection.send(Message::<Request<()>>::new(request, None)).await?;
+        return connection.receive().await;
+    }).await?;
+
+    println!("Received Message: {:?}", &received_message);
+
+    Ok(())
+}
+
+#[async_std::test]
+async fn spawned_connection_loopback_test() -> Result<(), TapsError> {
+    let mut local = LocalEndpoint::new();
+    local.with_address("127.0.0.1");
+    local.with_port(12026);
+
+    let mut remote = RemoteEndpoint::new();
+    remote.with_address("127.0.0.1");
+    remote.with_port(12026);
+
+    let mut tp = TransportProperties::default();
+    tp.require(SelectionProperty::Reliability);
+
+    let mut listener = Preconnection::new_raw(Some(local), None, Some(tp)).listen().await?;
+    listener.start().await?;
+    let mut connection = Preconnection::new_raw(None, Some(remote), Some(tp)).initiate().await?;
+
+    // Listeners and Connections can both be moved into other tasks
+    let echo = task::spawn(async move {
+        let mut accepted = listener.next().await.unwrap();
+        let message = accepted.receive().await?;
+        return accepted.send(message).await;
+    });
+
+    let received_message = task::spawn(async move {
+        connection.send(Message::new(b"echo".to_vec(), None)).await?;
+        return connection.receive().await;
+    }).await?;
+
+    echo.await?;
+    assert_eq!(received_message.data, b"echo".to_vec());
+
+    Ok(())
+}
+
+#[async_std::test]
+async fn rendezvous_test() -> Result<(), TapsError> {
+    // Each peer's local endpoint is the other's remote endpoint
+    let rendezvous_preconnection = |local_port: u16, remote_port: u16| {
+        let mut local = LocalEndpoint::new();
+        local.with_address("127.0.0.1");
+        local.with_port(local_port);
+
+        let mut remote = RemoteEndpoint::new();
+        remote.with_address("127.0.0.1");
+        remote.with_port(remote_port);
+
+        let mut tp = TransportProperties::default();
+        tp.require(SelectionProperty::Reliability);
+
+        return Preconnection::new_raw(Some(local), Some(remote), Some(tp));
+    };
+
+    // Both peers rendezvous at the same time
+    let (connection, peer_connection) = futures::join!(
+        rendezvous_preconnection(12005, 12006).rendezvous(),
+        rendezvous_preconnection(12006, 12005).rendezvous());
+    let mut connection = connection?;
+    let mut peer_connection = peer_connection?;
+
+    peer_connection.send(Message::new(b"hello".to_vec(), None)).await?;
+
+    let received_message = connection.receive().await?;
+    assert_eq!(received_message.data, b"hello".to_vec());
+
+    Ok(())
+}
+
+#[async_std::test]
+async fn resolve_test() -> Result<(), TapsError> {
+    let mut local = LocalEndpoint::new();
+    local.with_address("0.0.0.0");
+
+    let mut remote = RemoteEndpoint::new();
+    remote.with_host_name("gla.ac.uk");
+    remote.with_port(80);
+    let tp = TransportProperties::default();
+
+    let preconnection = Preconnection::new_raw(Some(local), Some(remote), Some(tp));
+
+    let resolved = preconnection.resolve().await?;
+    assert!(resolved.local.iter().all(|c| c.source == CandidateSource::Literal));
+    assert!(resolved.remote.iter().all(|c| c.source == CandidateSource::Dns));
+    assert!(!resolved.candidates.is_empty());
+
+    // Local addresses are only paired with remote addresses of the same family, and candidates are raced in rank order
+    assert!(resolved.candidates.iter().all(|c| c.remote.family == AddressFamily::Ipv4));
+    assert!(resolved.candidates.windows(2).all(|c| c[0].rank >= c[1].rank));
+
+    // Pin the candidates to the highest ranked protocol stack
+    let protocol = resolved.candidates[0].protocol;
+    let pinned = resolved.candidates.into_iter().filter(|c| c.protocol == protocol).collect();
+    let mut connection = preconnection.initiate_with_candidates(pinned).await?;
+    connection.close().await?;
+
+    Ok(())
+}
+
+#[async_std::test]
+async fn resolve_literal_test() -> Result<(), TapsError> {
+    let peer_listener = async_std::net::TcpListener::bind("127.0.0.1:12027").await?;
+
+    let mut local = LocalEndpoint::new();
+    local.with_address("127.0.0.1");
+
+    let mut remote = RemoteEndpoint::new();
+    remote.with_address("127.0.0.1");
+    remote.with_port(12027);
+
+    let mut tp = TransportProperties::default();
+    tp.require(SelectionProperty::Reliability);
+
+    let preconnection = Preconnection::new_raw(Some(local), Some(remote), Some(tp));
+
+    // Literal addresses are used as given, without DNS
+    let resolved = preconnection.resolve().await?;
+    assert!(resolved.local.iter().all(|c| c.source == CandidateSource::Literal));
+    assert_eq!(resolved.remote.len(), 1);
+    assert_eq!(resolved.remote[0].address, "127.0.0.1:12027".parse().unwrap());
+    assert_eq!(resolved.remote[0].source, CandidateSource::Literal);
+    assert!(resolved.candidates.iter().all(|c| c.remote.family == AddressFamily::Ipv4));
+    assert!(resolved.candidates.windows(2).all(|c| c[0].rank >= c[1].rank));
+
+    // Pin the candidates to TCP
+    let pinned = resolved.candidates.into_iter().filter(|c| c.protocol == "tcp").collect();
+    let _connection = preconnection.initiate_with_candidates(pinned).await?;
+
+    let (_stream, peer_addr) = peer_listener.accept().await?;
+    assert!(peer_addr.ip().is_loopback());
+
+    Ok(())
+}
+
+#[async_std::test]
+async fn udp_listener_test() -> Result<(), TapsError> {
+    let mut local = LocalEndpoint::new();
+    local.with_address("127.0.0.1");
+    local.with_port(12007);
+
+    let mut tp = TransportProperties::default();
+    tp.add(SelectionProperty::Reliability, PreferenceLevel::Prohibit);
+    tp.add(SelectionProperty::PreserveOrder, PreferenceLevel::Ignore);
+    tp.add(SelectionProperty::CongestionControl, PreferenceLevel::Ignore);
+
+    let preconnection = Preconnection::new_raw(Some(local), None, Some(tp));
+    let mut listener = preconnection.listen().await?;
+    listener.start().await?;
+
+    let peer_a = async_std::net::UdpSocket::bind("127.0.0.1:0").await?;
+    let peer_b = async_std::net::UdpSocket::bind("127.0.0.1:0").await?;
+    peer_a.send_to(b"a1", "127.0.0.1:12007").await?;
+    peer_b.send_to(b"b1", "127.0.0.1:12007").await?;
+
+    // One Connection per peer, each given the first datagram of its peer
+    let mut first = listener.next().await.unwrap();
+    let mut second = listener.next().await.unwrap();
+    let first_data = first.receive().await?.data;
+    let second_data = second.receive().await?.data;
+    let (mut connection_a, mut connection_b) = match first_data.as_slice() {
+        b"a1" => (first, second),
+        _ => (second, first),
+    };
+    let mut first_datagrams = vec![first_data, second_data];
+    first_datagrams.sort();
+    assert_eq!(first_datagrams, vec![b"a1".to_vec(), b"b1".to_vec()]);
+
+    // Later datagrams go to the Connection of their peer
+    peer_b.send_to(b"b2", "127.0.0.1:12007").await?;
+    peer_a.send_to(b"a2", "127.0.0.1:12007").await?;
+    assert_eq!(connection_a.receive().await?.data, b"a2".to_vec());
+    assert_eq!(connection_b.receive().await?.data, b"b2".to_vec());
+
+    // Replies are sent from the listener's socket
+    connection_a.send(Message::new(b"reply".to_vec(), None)).await?;
+    let mut buf = [0; 16];
+    let (len, from) = peer_a.recv_from(&mut buf).await?;
+    assert_eq!(&buf[..len], b"reply");
+    assert_eq!(from, "127.0.0.1:12007".parse().unwrap());
+
+    Ok(())
+}